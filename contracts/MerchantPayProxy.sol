@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+/// @notice ERC-1967 proxy for the MerchantPay Stylus program.
+/// Every call is delegated to the implementation stored in the ERC-1967 slot.
+/// Upgrades go through `upgradeTo` on MerchantPay itself (UUPS), which only the
+/// MerchantPay owner can call, so the proxy has no admin functions of its own.
+/// Once `upgradeTo` succeeds the proxy delegates `migrate()` to the new
+/// implementation in the same call, reverting the upgrade if migration fails.
+contract MerchantPayProxy {
+    /// @dev bytes32(uint256(keccak256("eip1967.proxy.implementation")) - 1)
+    bytes32 internal constant IMPLEMENTATION_SLOT =
+        0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc;
+
+    bytes4 internal constant UPGRADE_TO_SELECTOR = bytes4(keccak256("upgradeTo(address)"));
+    bytes4 internal constant MIGRATE_SELECTOR = bytes4(keccak256("migrate()"));
+
+    event Upgraded(address indexed implementation);
+
+    /// @param implementation Activated MerchantPay program
+    /// @param data Calldata delegated once after deployment, normally `initialize(usdc)`
+    constructor(address implementation, bytes memory data) payable {
+        require(implementation.code.length > 0, "MerchantPayProxy: no code");
+        assembly {
+            sstore(IMPLEMENTATION_SLOT, implementation)
+        }
+        require(_implementation() == implementation, "MerchantPayProxy: bad slot");
+        emit Upgraded(implementation);
+
+        if (data.length > 0) {
+            _delegate(implementation, data);
+        }
+    }
+
+    /// @dev Implementation stored in the low 20 bytes of the ERC-1967 slot
+    function _implementation() internal view returns (address implementation) {
+        assembly {
+            implementation := sload(IMPLEMENTATION_SLOT)
+        }
+    }
+
+    /// @dev Delegates `data` to `implementation`, bubbling up any revert
+    function _delegate(address implementation, bytes memory data) internal returns (bytes memory) {
+        (bool ok, bytes memory result) = implementation.delegatecall(data);
+        if (!ok) {
+            assembly {
+                revert(add(result, 32), mload(result))
+            }
+        }
+        return result;
+    }
+
+    fallback() external payable {
+        if (msg.sig == UPGRADE_TO_SELECTOR) {
+            // The program cannot call back into itself, so the proxy runs the migration
+            bytes memory result = _delegate(_implementation(), msg.data);
+            _delegate(_implementation(), abi.encodeWithSelector(MIGRATE_SELECTOR));
+            assembly {
+                return(add(result, 32), mload(result))
+            }
+        }
+
+        assembly {
+            let implementation := sload(IMPLEMENTATION_SLOT)
+            calldatacopy(0, 0, calldatasize())
+            let ok := delegatecall(gas(), implementation, 0, calldatasize(), 0, 0)
+            returndatacopy(0, 0, returndatasize())
+            switch ok
+            case 0 { revert(0, returndatasize()) }
+            default { return(0, returndatasize()) }
+        }
+    }
+}
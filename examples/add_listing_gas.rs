@@ -0,0 +1,97 @@
+//! Gas benchmark for `add_listing` on a deployed MerchantPay contract.
+//! Adds `BENCH_LISTINGS` listings from a single seller and prints the gas used by
+//! the add at each power of ten, showing that the cost stays flat as the seller's
+//! catalogue grows. The payment token given by `BENCH_TOKEN` must already be accepted.
+
+use ethers::{
+    middleware::SignerMiddleware,
+    prelude::abigen,
+    providers::{Http, Middleware, Provider},
+    signers::{LocalWallet, Signer},
+    types::{Address, U256},
+    utils::keccak256,
+};
+use dotenv::dotenv;
+use eyre::eyre;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Your private key file path.
+const PRIV_KEY_PATH: &str = "PRIV_KEY_PATH";
+
+/// Stylus RPC endpoint url.
+const RPC_URL: &str = "RPC_URL";
+
+/// Deployed program address.
+const STYLUS_CONTRACT_ADDRESS: &str = "STYLUS_CONTRACT_ADDRESS";
+
+/// Accepted payment token the benchmark listings are priced in.
+const BENCH_TOKEN: &str = "BENCH_TOKEN";
+
+/// Number of listings to add, defaults to 5000.
+const BENCH_LISTINGS: &str = "BENCH_LISTINGS";
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    dotenv().ok();
+    let priv_key_path =
+        std::env::var(PRIV_KEY_PATH).map_err(|_| eyre!("No {} env var set", PRIV_KEY_PATH))?;
+    let rpc_url = std::env::var(RPC_URL).map_err(|_| eyre!("No {} env var set", RPC_URL))?;
+    let contract_address = std::env::var(STYLUS_CONTRACT_ADDRESS)
+        .map_err(|_| eyre!("No {} env var set", STYLUS_CONTRACT_ADDRESS))?;
+    let token: Address = std::env::var(BENCH_TOKEN)
+        .map_err(|_| eyre!("No {} env var set", BENCH_TOKEN))?
+        .parse()?;
+    let total: u64 = std::env::var(BENCH_LISTINGS)
+        .unwrap_or_else(|_| "5000".to_string())
+        .parse()?;
+    abigen!(
+        MerchantPay,
+        r#"[
+            function addListing(bytes32 id, address token, uint256 rate, uint256 quantity, bool escrow, bytes32 metadata) external
+        ]"#
+    );
+
+    let provider = Provider::<Http>::try_from(rpc_url)?;
+    let address: Address = contract_address.parse()?;
+
+    let privkey = read_secret_from_file(&priv_key_path)?;
+    let wallet = LocalWallet::from_str(&privkey)?;
+    let chain_id = provider.get_chainid().await?.as_u64();
+    let client = Arc::new(SignerMiddleware::new(
+        provider,
+        wallet.clone().with_chain_id(chain_id),
+    ));
+
+    let merchant_pay = MerchantPay::new(address, client);
+    let run = keccak256(format!("{:?}-{}", wallet.address(), chain_id).as_bytes());
+
+    println!("{:>10} {:>12}", "listings", "gas used");
+    let mut checkpoint = 1;
+    for i in 1..=total {
+        // Fresh id per listing so every call takes the insert path
+        let id = keccak256([run.as_slice(), &i.to_be_bytes()].concat());
+        let call = merchant_pay.add_listing(id, token, U256::from(1), U256::from(1), false, [0u8; 32]);
+        let receipt = call
+            .send()
+            .await?
+            .await?
+            .ok_or_else(|| eyre!("No receipt for listing {}", i))?;
+
+        if i == checkpoint || i == total {
+            let gas_used = receipt.gas_used.unwrap_or_default();
+            println!("{:>10} {:>12}", i, gas_used);
+            checkpoint *= 10;
+        }
+    }
+    Ok(())
+}
+
+fn read_secret_from_file(fpath: &str) -> eyre::Result<String> {
+    let f = std::fs::File::open(fpath)?;
+    let mut buf_reader = BufReader::new(f);
+    let mut secret = String::new();
+    buf_reader.read_line(&mut secret)?;
+    Ok(secret.trim().to_string())
+}
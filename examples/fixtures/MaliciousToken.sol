@@ -0,0 +1,67 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+interface IMerchantPay {
+    function payForListing(bytes32 id, address seller, uint256 quantity, uint256 tip, uint256 maxAmount) external returns (uint256);
+}
+
+/// @notice ERC20 whose `transferFrom` tries to buy from MerchantPay again
+/// before returning. Used by `examples/reentrancy_attack.rs`.
+contract MaliciousToken {
+    mapping(address => uint256) public balanceOf;
+    mapping(address => mapping(address => uint256)) public allowance;
+
+    IMerchantPay public target;
+    bytes32 public listingId;
+    address public seller;
+
+    bool public reentryAttempted;
+    bool public reentryBlocked;
+    bytes public reentryRevertData;
+
+    function mint(address to, uint256 amount) external {
+        balanceOf[to] += amount;
+    }
+
+    function approve(address spender, uint256 amount) external returns (bool) {
+        allowance[msg.sender][spender] = amount;
+        return true;
+    }
+
+    /// @notice Re-enter `payForListing` on the next pull from `target`
+    function arm(IMerchantPay target_, bytes32 listingId_, address seller_) external {
+        target = target_;
+        listingId = listingId_;
+        seller = seller_;
+        reentryAttempted = false;
+        reentryBlocked = false;
+        delete reentryRevertData;
+    }
+
+    function transfer(address to, uint256 amount) external returns (bool) {
+        _move(msg.sender, to, amount);
+        return true;
+    }
+
+    function transferFrom(address from, address to, uint256 amount) external returns (bool) {
+        allowance[from][msg.sender] -= amount;
+        _move(from, to, amount);
+
+        if (msg.sender == address(target) && !reentryAttempted) {
+            reentryAttempted = true;
+            // An unlimited bound, so only the re-entry itself can make this fail
+            try target.payForListing(listingId, seller, 1, 0, type(uint256).max) {
+                reentryBlocked = false;
+            } catch (bytes memory reason) {
+                reentryBlocked = true;
+                reentryRevertData = reason;
+            }
+        }
+        return true;
+    }
+
+    function _move(address from, address to, uint256 amount) internal {
+        balanceOf[from] -= amount;
+        balanceOf[to] += amount;
+    }
+}
@@ -0,0 +1,127 @@
+//! Checks that MerchantPay rejects re-entry from a malicious payment token.
+//! Expects `examples/fixtures/MaliciousToken.sol` deployed at `MALICIOUS_TOKEN`
+//! and the private key to belong to the MerchantPay owner. The script whitelists
+//! the token, lists one item priced in it and buys it; during the purchase the
+//! token's `transferFrom` tries to buy again, which must revert and leave exactly
+//! one unit sold. The SDK rejects re-entry before MerchantPay runs, so the revert
+//! data is printed for reference but not checked.
+
+use ethers::{
+    middleware::SignerMiddleware,
+    prelude::abigen,
+    providers::{Http, Middleware, Provider},
+    signers::{LocalWallet, Signer},
+    types::{Address, U256},
+    utils::keccak256,
+};
+use dotenv::dotenv;
+use eyre::eyre;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Your private key file path.
+const PRIV_KEY_PATH: &str = "PRIV_KEY_PATH";
+
+/// Stylus RPC endpoint url.
+const RPC_URL: &str = "RPC_URL";
+
+/// Deployed program address.
+const STYLUS_CONTRACT_ADDRESS: &str = "STYLUS_CONTRACT_ADDRESS";
+
+/// Deployed MaliciousToken fixture address.
+const MALICIOUS_TOKEN: &str = "MALICIOUS_TOKEN";
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    dotenv().ok();
+    let priv_key_path =
+        std::env::var(PRIV_KEY_PATH).map_err(|_| eyre!("No {} env var set", PRIV_KEY_PATH))?;
+    let rpc_url = std::env::var(RPC_URL).map_err(|_| eyre!("No {} env var set", RPC_URL))?;
+    let contract_address = std::env::var(STYLUS_CONTRACT_ADDRESS)
+        .map_err(|_| eyre!("No {} env var set", STYLUS_CONTRACT_ADDRESS))?;
+    let token_address = std::env::var(MALICIOUS_TOKEN)
+        .map_err(|_| eyre!("No {} env var set", MALICIOUS_TOKEN))?;
+    abigen!(
+        MerchantPay,
+        r#"[
+            function setTokenAccepted(address token, bool accepted) external
+            function addListing(bytes32 id, address token, uint256 rate, uint256 quantity, bool escrow, bytes32 metadata) external
+            function payForListing(bytes32 id, address seller, uint256 quantity, uint256 tip, uint256 maxAmount) external returns (uint256)
+            function getListing(bytes32 id, address seller) external view returns ((bytes32,address,address,uint256,uint256,uint8,bool,uint256,bytes32,uint256,uint256,uint256,uint256,uint256,uint256))
+        ]"#
+    );
+    abigen!(
+        MaliciousToken,
+        r#"[
+            function mint(address to, uint256 amount) external
+            function approve(address spender, uint256 amount) external returns (bool)
+            function arm(address target, bytes32 listingId, address seller) external
+            function reentryAttempted() external view returns (bool)
+            function reentryBlocked() external view returns (bool)
+            function reentryRevertData() external view returns (bytes)
+        ]"#
+    );
+
+    let provider = Provider::<Http>::try_from(rpc_url)?;
+    let address: Address = contract_address.parse()?;
+    let token_address: Address = token_address.parse()?;
+
+    let privkey = read_secret_from_file(&priv_key_path)?;
+    let wallet = LocalWallet::from_str(&privkey)?;
+    let chain_id = provider.get_chainid().await?.as_u64();
+    let client = Arc::new(SignerMiddleware::new(
+        provider,
+        wallet.clone().with_chain_id(chain_id),
+    ));
+
+    let merchant_pay = MerchantPay::new(address, client.clone());
+    let token = MaliciousToken::new(token_address, client);
+    let me = wallet.address();
+
+    // Two units in stock: a successful re-entry would sell both in one payment
+    let id = keccak256(format!("reentrancy-{:?}-{}", me, chain_id).as_bytes());
+    let rate = U256::from(100);
+    merchant_pay.set_token_accepted(token_address, true).send().await?.await?;
+    merchant_pay
+        .add_listing(id, token_address, rate, U256::from(2), false, [0u8; 32])
+        .send()
+        .await?
+        .await?;
+
+    token.mint(me, rate * 10).send().await?.await?;
+    token.approve(address, rate * 10).send().await?.await?;
+    token.arm(address, id, me).send().await?.await?;
+
+    merchant_pay
+        .pay_for_listing(id, me, U256::from(1), U256::zero(), rate)
+        .send()
+        .await?
+        .await?;
+
+    let attempted = token.reentry_attempted().call().await?;
+    let blocked = token.reentry_blocked().call().await?;
+    let revert_data = token.reentry_revert_data().call().await?;
+    let listing = merchant_pay.get_listing(id, me).call().await?;
+    println!("re-entry attempted = {}", attempted);
+    println!("re-entry blocked = {}", blocked);
+    println!("revert data = 0x{}", hex::encode(&revert_data));
+    println!("quantity left = {}", listing.4);
+
+    if !attempted || !blocked {
+        return Err(eyre!("re-entry was not rejected"));
+    }
+    if listing.4 != U256::from(1) {
+        return Err(eyre!("expected exactly one unit sold, {} of 2 left", listing.4));
+    }
+    println!("re-entry rejected");
+    Ok(())
+}
+
+fn read_secret_from_file(fpath: &str) -> eyre::Result<String> {
+    let f = std::fs::File::open(fpath)?;
+    let mut buf_reader = BufReader::new(f);
+    let mut secret = String::new();
+    buf_reader.read_line(&mut secret)?;
+    Ok(secret.trim().to_string())
+}
@@ -0,0 +1,124 @@
+//! Checks that listings survive an upgrade of MerchantPay behind its ERC-1967 proxy.
+//! Expects `contracts/MerchantPayProxy.sol` deployed at `STYLUS_CONTRACT_ADDRESS`
+//! pointing at a v1 implementation built from a revision at storage version 1,
+//! before `Listing.listed_at` was appended, and the current build activated at
+//! `V2_IMPLEMENTATION`. The private key must belong to the MerchantPay owner. The script adds a listing under v1, upgrades (which migrates in
+//! the same transaction) and checks the listing reads back with every v1 field
+//! unchanged and the new field appended as zero.
+
+use ethers::{
+    middleware::SignerMiddleware,
+    prelude::abigen,
+    providers::{Http, Middleware, Provider},
+    signers::{LocalWallet, Signer},
+    types::{Address, U256},
+    utils::keccak256,
+};
+use dotenv::dotenv;
+use eyre::eyre;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Your private key file path.
+const PRIV_KEY_PATH: &str = "PRIV_KEY_PATH";
+
+/// Stylus RPC endpoint url.
+const RPC_URL: &str = "RPC_URL";
+
+/// Deployed proxy address.
+const STYLUS_CONTRACT_ADDRESS: &str = "STYLUS_CONTRACT_ADDRESS";
+
+/// Activated v2 implementation address.
+const V2_IMPLEMENTATION: &str = "V2_IMPLEMENTATION";
+
+/// Accepted payment token the listing is priced in.
+const LISTING_TOKEN: &str = "LISTING_TOKEN";
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    dotenv().ok();
+    let priv_key_path =
+        std::env::var(PRIV_KEY_PATH).map_err(|_| eyre!("No {} env var set", PRIV_KEY_PATH))?;
+    let rpc_url = std::env::var(RPC_URL).map_err(|_| eyre!("No {} env var set", RPC_URL))?;
+    let contract_address = std::env::var(STYLUS_CONTRACT_ADDRESS)
+        .map_err(|_| eyre!("No {} env var set", STYLUS_CONTRACT_ADDRESS))?;
+    let v2: Address = std::env::var(V2_IMPLEMENTATION)
+        .map_err(|_| eyre!("No {} env var set", V2_IMPLEMENTATION))?
+        .parse()?;
+    let token: Address = std::env::var(LISTING_TOKEN)
+        .map_err(|_| eyre!("No {} env var set", LISTING_TOKEN))?
+        .parse()?;
+    abigen!(
+        MerchantPay,
+        r#"[
+            function addListing(bytes32 id, address token, uint256 rate, uint256 quantity, bool escrow, bytes32 metadata) external
+            function getListing(bytes32 id, address seller) external view returns ((bytes32,address,address,uint256,uint256,uint8,bool,uint256,bytes32,uint256,uint256,uint256,uint256,uint256))
+            function implementation() external view returns (address)
+            function upgradeTo(address implementation) external
+            function storageVersion() external view returns (uint256)
+        ]"#
+    );
+
+    let provider = Provider::<Http>::try_from(rpc_url)?;
+    let address: Address = contract_address.parse()?;
+
+    let privkey = read_secret_from_file(&priv_key_path)?;
+    let wallet = LocalWallet::from_str(&privkey)?;
+    let chain_id = provider.get_chainid().await?.as_u64();
+    let client = Arc::new(SignerMiddleware::new(
+        provider,
+        wallet.clone().with_chain_id(chain_id),
+    ));
+
+    let merchant_pay = MerchantPay::new(address, client.clone());
+    let me = wallet.address();
+
+    let v1 = merchant_pay.implementation().call().await?;
+    let v1_version = merchant_pay.storage_version().call().await?;
+    println!("v1 implementation = {:?}, storage version = {}", v1, v1_version);
+
+    let id = keccak256(format!("upgrade-{:?}-{}", me, chain_id).as_bytes());
+    let metadata = keccak256(b"listed under v1");
+    merchant_pay
+        .add_listing(id, token, U256::from(100), U256::from(3), true, metadata)
+        .send()
+        .await?
+        .await?;
+
+    // Compare raw return data, since v2 returns a longer tuple than the v1 ABI above
+    let read_listing = merchant_pay.get_listing(id, me);
+    let before = client.call(&read_listing.tx, None).await?;
+
+    merchant_pay.upgrade_to(v2).send().await?.await?;
+
+    let implementation = merchant_pay.implementation().call().await?;
+    let version = merchant_pay.storage_version().call().await?;
+    let after = client.call(&read_listing.tx, None).await?;
+    println!("v2 implementation = {:?}, storage version = {}", implementation, version);
+    println!("listing under v1 = 0x{}", hex::encode(&before));
+    println!("listing under v2 = 0x{}", hex::encode(&after));
+
+    if implementation != v2 {
+        return Err(eyre!("proxy still points at {:?}", implementation));
+    }
+    if version != U256::from(2) || version <= v1_version {
+        return Err(eyre!("storage version moved from {} to {}, expected 2", v1_version, version));
+    }
+    if after.len() <= before.len() || after[..before.len()] != before[..] {
+        return Err(eyre!("v1 listing fields changed across the upgrade"));
+    }
+    if after[before.len()..].iter().any(|byte| *byte != 0) {
+        return Err(eyre!("appended listing fields are not zero for a v1 listing"));
+    }
+    println!("listing preserved across upgrade");
+    Ok(())
+}
+
+fn read_secret_from_file(fpath: &str) -> eyre::Result<String> {
+    let f = std::fs::File::open(fpath)?;
+    let mut buf_reader = BufReader::new(f);
+    let mut secret = String::new();
+    buf_reader.read_line(&mut secret)?;
+    Ok(secret.trim().to_string())
+}
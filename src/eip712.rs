@@ -0,0 +1,79 @@
+//! EIP-712 typed structured data helpers
+//!
+//! Provides the domain separator and digest construction from
+//! [EIP-712](https://eips.ethereum.org/EIPS/eip-712), plus signer recovery
+//! through the `ecrecover` precompile. Struct hashes are built by the caller
+//! from 32-byte words with [`encode_words`].
+
+// Imported packages
+use alloc::vec::Vec;
+use alloy_primitives::{address, b256, Address, B256, U256};
+use stylus_sdk::{
+    block,
+    call::{static_call, Call},
+    contract,
+    crypto::keccak,
+};
+
+/// keccak256("EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)")
+pub const DOMAIN_TYPEHASH: B256 =
+    b256!("8b73c3c69bb8fe3d512ecc4cf759cc79239f7b179b0ffacaa9a75d522b39400f");
+
+/// Address of the `ecrecover` precompile
+const ECRECOVER: Address = address!("0000000000000000000000000000000000000001");
+
+/// Upper bound on `s` for non-malleable signatures (secp256k1n / 2)
+const MAX_S: B256 = b256!("7fffffffffffffffffffffffffffffff5d576e7357a4501ddfe92f46681b20a0");
+
+/// Concatenates ABI words, as used by `encodeData`
+pub fn encode_words(words: &[B256]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(words.len() * 32);
+    for word in words {
+        encoded.extend_from_slice(word.as_slice());
+    }
+    encoded
+}
+
+/// Encodes a `uint256` as an ABI word
+pub fn word(value: U256) -> B256 {
+    B256::from(value.to_be_bytes::<32>())
+}
+
+/// Domain separator for this contract on the current chain
+pub fn domain_separator(name: &str, version: &str) -> B256 {
+    keccak(encode_words(&[
+        DOMAIN_TYPEHASH,
+        keccak(name.as_bytes()),
+        keccak(version.as_bytes()),
+        word(U256::from(block::chainid())),
+        contract::address().into_word(),
+    ]))
+}
+
+/// Digest to be signed for `struct_hash` under `domain_separator`
+pub fn hash_typed_data(domain_separator: B256, struct_hash: B256) -> B256 {
+    let mut encoded = Vec::with_capacity(66);
+    encoded.extend_from_slice(b"\x19\x01");
+    encoded.extend_from_slice(domain_separator.as_slice());
+    encoded.extend_from_slice(struct_hash.as_slice());
+    keccak(encoded)
+}
+
+/// Recovers the signer of `digest`, rejecting malleable or malformed signatures
+pub fn recover(digest: B256, v: u8, r: B256, s: B256) -> Option<Address> {
+    if (v != 27 && v != 28) || s > MAX_S {
+        return None;
+    }
+
+    let input = encode_words(&[digest, word(U256::from(v)), r, s]);
+    let output = static_call(Call::new(), ECRECOVER, &input).ok()?;
+    if output.len() != 32 {
+        return None;
+    }
+
+    let signer = Address::from_slice(&output[12..]);
+    if signer == Address::ZERO {
+        return None;
+    }
+    Some(signer)
+}
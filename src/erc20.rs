@@ -6,19 +6,33 @@
 //! You can configure the behavior of [`Erc20`] via the [`Erc20Params`] trait,
 //! which allows specifying the name, symbol, and decimals of the token.
 //!
+//! Gasless approvals are supported through EIP-2612 `permit`, with the EIP-712
+//! domain derived from [`Erc20Params::NAME`] and the current chain id.
+//!
 //! Note that this code is unaudited and not fit for production use.
 
 // Imported packages
 use alloc::string::String;
-use alloy_primitives::{Address, U256};
+use alloy_primitives::{b256, Address, B256, U256};
 use alloy_sol_types::sol;
 use core::marker::PhantomData;
 use stylus_sdk::{
+    block,
+    crypto::keccak,
     evm,
     msg,
     prelude::*,
 };
 
+use crate::eip712::{self, encode_words, word};
+
+/// keccak256("Permit(address owner,address spender,uint256 value,uint256 nonce,uint256 deadline)")
+pub const PERMIT_TYPEHASH: B256 =
+    b256!("6e71edae12b1b97f4d1f60370fef10105fa2faae0126114a169c64845d6126c9");
+
+/// EIP-712 domain version used for permits
+pub const PERMIT_VERSION: &str = "1";
+
 pub trait Erc20Params {
     /// Immutable token name
     const NAME: &'static str;
@@ -39,6 +53,8 @@ sol_storage! {
         mapping(address => mapping(address => uint256)) allowances;
         /// The total supply of the token
         uint256 total_supply;
+        /// Maps owners to their next permit nonce
+        mapping(address => uint256) nonces;
         /// Used to allow [`Erc20Params`]
         PhantomData<T> phantom;
     }
@@ -51,6 +67,8 @@ sol! {
 
     error InsufficientBalance(address from, uint256 have, uint256 want);
     error InsufficientAllowance(address owner, address spender, uint256 have, uint256 want);
+    error ERC2612ExpiredSignature(uint256 deadline);
+    error ERC2612InvalidSigner(address signer, address owner);
 }
 
 /// Represents the ways methods may fail.
@@ -58,6 +76,8 @@ sol! {
 pub enum Erc20Error {
     InsufficientBalance(InsufficientBalance),
     InsufficientAllowance(InsufficientAllowance),
+    ERC2612ExpiredSignature(ERC2612ExpiredSignature),
+    ERC2612InvalidSigner(ERC2612InvalidSigner),
 }
 
 // These methods aren't exposed to other contracts
@@ -222,4 +242,68 @@ impl<T: Erc20Params> Erc20<T> {
     pub fn allowance(&self, owner: Address, spender: Address) -> U256 {
         self.allowances.getter(owner).get(spender)
     }
+
+    /// Approves `value` of `owner`'s tokens to `spender` using `owner`'s EIP-712 signature
+    /// (EIP-2612). The signature must be submitted before `deadline`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn permit(
+        &mut self,
+        owner: Address,
+        spender: Address,
+        value: U256,
+        deadline: U256,
+        v: u8,
+        r: B256,
+        s: B256,
+    ) -> Result<(), Erc20Error> {
+        if U256::from(block::timestamp()) > deadline {
+            return Err(Erc20Error::ERC2612ExpiredSignature(ERC2612ExpiredSignature {
+                deadline,
+            }));
+        }
+
+        // Consumes the owner's current nonce
+        let mut nonce = self.nonces.setter(owner);
+        let current_nonce = nonce.get();
+        nonce.set(current_nonce + U256::from(1));
+
+        let struct_hash = keccak(encode_words(&[
+            PERMIT_TYPEHASH,
+            owner.into_word(),
+            spender.into_word(),
+            word(value),
+            word(current_nonce),
+            word(deadline),
+        ]));
+        let digest = eip712::hash_typed_data(self.domain_separator(), struct_hash);
+
+        // An unrecoverable signature must never match, even for `owner == Address::ZERO`
+        let recovered = eip712::recover(digest, v, r, s);
+        let signer = recovered.unwrap_or(Address::ZERO);
+        if recovered.is_none() || signer != owner {
+            return Err(Erc20Error::ERC2612InvalidSigner(ERC2612InvalidSigner {
+                signer,
+                owner,
+            }));
+        }
+
+        self.allowances.setter(owner).insert(spender, value);
+        evm::log(Approval {
+            owner,
+            spender,
+            value,
+        });
+        Ok(())
+    }
+
+    /// Returns the next permit nonce of `owner`
+    pub fn nonces(&self, owner: Address) -> U256 {
+        self.nonces.get(owner)
+    }
+
+    /// EIP-712 domain separator used to sign permits
+    #[selector(name = "DOMAIN_SEPARATOR")]
+    pub fn domain_separator(&self) -> B256 {
+        eip712::domain_separator(T::NAME, PERMIT_VERSION)
+    }
 }
\ No newline at end of file
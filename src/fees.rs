@@ -0,0 +1,152 @@
+//! Platform fee schedule
+//!
+//! [`FeeSchedule`] computes the charge MerchantPay takes from every payment.
+//! Fees are expressed in basis points of the full order price, with a global
+//! rate set by the owner and optional tiers that override it for individual
+//! sellers. Every computed fee is then clamped between a per-token floor and
+//! cap, since absolute amounts only make sense in a given token's units.
+
+// Imported packages
+use alloy_primitives::{Address, U256};
+use alloy_sol_types::sol;
+use stylus_sdk::{evm, prelude::*};
+
+use crate::{InvalidAmount, InvalidFee, MerchantPayError};
+
+/// 100% expressed in basis points
+pub const MAX_BPS: u64 = 10_000;
+
+/// Rate applied by a fresh deployment (0.1%)
+pub const DEFAULT_FEE_BPS: u64 = 10;
+
+sol_storage! {
+    /// FeeSchedule holds the fee configuration set by the owner.
+    pub struct FeeSchedule {
+        /// Rate applied to sellers without a tier
+        uint256 rate_bps;
+        /// Smallest fee charged on a non-zero payment, per token
+        mapping(address => uint256) min_fee;
+        /// Largest fee charged on any payment, per token, zero meaning uncapped
+        mapping(address => uint256) max_fee;
+        /// Maps tier ids to their rate
+        mapping(uint256 => uint256) tier_rate_bps;
+        /// Whether a tier id has been defined
+        mapping(uint256 => bool) tier_defined;
+        /// Maps sellers to their tier, zero meaning the global rate
+        mapping(address => uint256) seller_tier;
+    }
+}
+
+// Declare events
+sol! {
+    event FeeRateUpdated(uint256 oldRateBps, uint256 newRateBps);
+    event FeeTierUpdated(uint256 indexed tier, uint256 rateBps);
+    event SellerFeeTierUpdated(address indexed seller, uint256 indexed tier);
+    event FeeBoundsUpdated(address indexed token, uint256 minFee, uint256 maxFee);
+}
+
+impl FeeSchedule {
+    /// Sets the global rate
+    pub fn set_rate(&mut self, rate_bps: U256) -> Result<(), MerchantPayError> {
+        if rate_bps > U256::from(MAX_BPS) {
+            return Err(MerchantPayError::InvalidFee(InvalidFee{}));
+        }
+        let old_rate_bps = self.rate_bps.get();
+        self.rate_bps.set(rate_bps);
+
+        evm::log(FeeRateUpdated {
+            oldRateBps: old_rate_bps,
+            newRateBps: rate_bps,
+        });
+        Ok(())
+    }
+
+    /// Creates or changes tier `tier`; tier zero is reserved for the global rate
+    pub fn set_tier(&mut self, tier: U256, rate_bps: U256) -> Result<(), MerchantPayError> {
+        if tier == U256::ZERO || rate_bps > U256::from(MAX_BPS) {
+            return Err(MerchantPayError::InvalidFee(InvalidFee{}));
+        }
+        self.tier_rate_bps.insert(tier, rate_bps);
+        self.tier_defined.insert(tier, true);
+
+        evm::log(FeeTierUpdated {
+            tier,
+            rateBps: rate_bps,
+        });
+        Ok(())
+    }
+
+    /// Moves `seller` onto `tier`, or back onto the global rate when `tier` is zero
+    pub fn set_seller_tier(&mut self, seller: Address, tier: U256) -> Result<(), MerchantPayError> {
+        if tier != U256::ZERO && !self.tier_defined.get(tier) {
+            return Err(MerchantPayError::InvalidFee(InvalidFee{}));
+        }
+        self.seller_tier.insert(seller, tier);
+
+        evm::log(SellerFeeTierUpdated { seller, tier });
+        Ok(())
+    }
+
+    /// Sets the fee floor and cap for `token`; a zero cap leaves fees uncapped
+    pub fn set_bounds(&mut self, token: Address, min_fee: U256, max_fee: U256) -> Result<(), MerchantPayError> {
+        if max_fee != U256::ZERO && max_fee < min_fee {
+            return Err(MerchantPayError::InvalidFee(InvalidFee{}));
+        }
+        self.min_fee.insert(token, min_fee);
+        self.max_fee.insert(token, max_fee);
+
+        evm::log(FeeBoundsUpdated {
+            token,
+            minFee: min_fee,
+            maxFee: max_fee,
+        });
+        Ok(())
+    }
+
+    /// Global rate
+    pub fn rate(&self) -> U256 {
+        self.rate_bps.get()
+    }
+
+    /// Tier assigned to `seller`
+    pub fn tier_of(&self, seller: Address) -> U256 {
+        self.seller_tier.get(seller)
+    }
+
+    /// Rate that applies to `seller` after tier overrides
+    pub fn rate_for(&self, seller: Address) -> U256 {
+        let tier = self.seller_tier.get(seller);
+        if tier == U256::ZERO {
+            return self.rate_bps.get();
+        }
+        self.tier_rate_bps.get(tier)
+    }
+
+    /// Fee owed by `seller` on a payment of `amount` in `token`; fails if `amount` is too large
+    /// to apply the rate to
+    pub fn fee_for(&self, seller: Address, token: Address, amount: U256) -> Result<U256, MerchantPayError> {
+        if amount == U256::ZERO {
+            return Ok(U256::ZERO);
+        }
+
+        let mut fee = amount
+            .checked_mul(self.rate_for(seller))
+            .ok_or(MerchantPayError::InvalidAmount(InvalidAmount{}))?
+            / U256::from(MAX_BPS);
+
+        let min_fee = self.min_fee.get(token);
+        if fee < min_fee {
+            fee = min_fee;
+        }
+        let max_fee = self.max_fee.get(token);
+        if max_fee != U256::ZERO && fee > max_fee {
+            fee = max_fee;
+        }
+
+        // Never charge more than the payment itself
+        if fee > amount {
+            fee = amount;
+        }
+        Ok(fee)
+    }
+}
@@ -0,0 +1,208 @@
+//! Merchant-issued invoices
+//!
+//! An [`Invoice`] is a one-off bill from a seller, optionally addressed to a
+//! single buyer, payable exactly once. If the due date passes before payment,
+//! the invoice's late fee is added on top of the amount. The issuer can void
+//! an invoice at any time before it is paid.
+//!
+//! Payment is collected by `pay_invoice` in `MerchantPay`, which charges the
+//! platform fee exactly as for a listing purchase.
+
+// Imported packages
+use alloy_primitives::{Address, B256, U256};
+use alloy_sol_types::sol;
+use stylus_sdk::{evm, prelude::*};
+
+use crate::{InvalidAmount, InvoiceNotFound, InvoiceNotOpen, MerchantPayError, Unauthorized};
+
+// Define InvoiceStatus enum
+#[derive(Default, Clone, Copy, PartialEq, Eq, StorageType)]
+pub enum InvoiceStatus {
+    #[default]
+    OPEN,
+    PAID,
+    VOID,
+}
+
+// Define Invoice struct
+#[derive(Default, Clone, StorageType)]
+pub struct Invoice {
+    id: U256,
+    issuer: Address,
+    buyer: Address,
+    token: Address,
+    amount: U256,
+    late_fee: U256,
+    due_date: U256,
+    reference: B256,
+    status: InvoiceStatus,
+    paid_by: Address,
+    paid_at: U256,
+}
+
+sol_storage! {
+    /// Invoices stores every invoice issued through MerchantPay.
+    pub struct Invoices {
+        /// Id of the most recently created invoice
+        uint256 invoice_count;
+        /// Maps invoice ids to invoices
+        mapping(uint256 => Invoice) invoices;
+    }
+}
+
+// Declare events
+sol! {
+    event InvoiceCreated(
+        uint256 indexed invoiceId,
+        address indexed issuer,
+        address indexed buyer,
+        address token,
+        uint256 amount,
+        uint256 lateFee,
+        uint256 dueDate,
+        bytes32 reference
+    );
+    event InvoicePaid(
+        uint256 indexed invoiceId,
+        address indexed issuer,
+        address indexed payer,
+        uint256 amount,
+        uint256 fee
+    );
+    event InvoiceVoided(uint256 indexed invoiceId, address indexed issuer);
+}
+
+impl Invoice {
+    pub fn issuer(&self) -> Address {
+        self.issuer
+    }
+
+    pub fn token(&self) -> Address {
+        self.token
+    }
+
+    /// Whether `payer` may settle the invoice; an invoice without a buyer is open to anyone
+    pub fn is_payable_by(&self, payer: Address) -> bool {
+        self.buyer == Address::ZERO || self.buyer == payer
+    }
+
+    /// Amount owed at `now`, including the late fee once the due date has passed
+    pub fn amount_due_at(&self, now: U256) -> U256 {
+        if self.due_date != U256::ZERO && now > self.due_date {
+            return self.amount + self.late_fee;
+        }
+        self.amount
+    }
+}
+
+impl Invoices {
+    /// Issues a new invoice from `issuer`
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        &mut self,
+        issuer: Address,
+        buyer: Address,
+        token: Address,
+        amount: U256,
+        due_date: U256,
+        reference: B256,
+        late_fee: U256,
+    ) -> Result<U256, MerchantPayError> {
+        // The late fee is added on top once overdue, so the total must fit as well
+        if amount == U256::ZERO || amount.checked_add(late_fee).is_none() {
+            return Err(MerchantPayError::InvalidAmount(InvalidAmount{}));
+        }
+
+        let invoice_id = self.invoice_count.get() + U256::from(1);
+        self.invoice_count.set(invoice_id);
+        self.invoices.setter(invoice_id).set(Invoice {
+            id: invoice_id,
+            issuer,
+            buyer,
+            token,
+            amount,
+            late_fee,
+            due_date,
+            reference,
+            status: InvoiceStatus::OPEN,
+            paid_by: Address::ZERO,
+            paid_at: U256::ZERO,
+        });
+
+        evm::log(InvoiceCreated {
+            invoiceId: invoice_id,
+            issuer,
+            buyer,
+            token,
+            amount,
+            lateFee: late_fee,
+            dueDate: due_date,
+            reference,
+        });
+        Ok(invoice_id)
+    }
+
+    pub fn invoice(&self, invoice_id: U256) -> Result<Invoice, MerchantPayError> {
+        let invoice = self.invoices.getter(invoice_id).get();
+        if invoice.issuer == Address::ZERO {
+            return Err(MerchantPayError::InvoiceNotFound(InvoiceNotFound{}));
+        }
+        Ok(invoice)
+    }
+
+    /// Returns the invoice if it is open and `payer` may settle it
+    pub fn payable(&self, invoice_id: U256, payer: Address) -> Result<Invoice, MerchantPayError> {
+        let invoice = self.invoice(invoice_id)?;
+        if invoice.status != InvoiceStatus::OPEN {
+            return Err(MerchantPayError::InvoiceNotOpen(InvoiceNotOpen{}));
+        }
+        if !invoice.is_payable_by(payer) {
+            return Err(MerchantPayError::Unauthorized(Unauthorized{}));
+        }
+        Ok(invoice)
+    }
+
+    /// Marks an open invoice as settled by `payer`
+    pub fn mark_paid(
+        &mut self,
+        invoice_id: U256,
+        payer: Address,
+        amount: U256,
+        fee: U256,
+        now: U256,
+    ) -> Result<(), MerchantPayError> {
+        let mut invoice = self.payable(invoice_id, payer)?;
+        invoice.status = InvoiceStatus::PAID;
+        invoice.paid_by = payer;
+        invoice.paid_at = now;
+        self.invoices.setter(invoice_id).set(invoice.clone());
+
+        evm::log(InvoicePaid {
+            invoiceId: invoice_id,
+            issuer: invoice.issuer,
+            payer,
+            amount,
+            fee,
+        });
+        Ok(())
+    }
+
+    /// Voids an open invoice on behalf of its issuer
+    pub fn void(&mut self, caller: Address, invoice_id: U256) -> Result<(), MerchantPayError> {
+        let mut invoice = self.invoice(invoice_id)?;
+        if invoice.issuer != caller {
+            return Err(MerchantPayError::Unauthorized(Unauthorized{}));
+        }
+        if invoice.status != InvoiceStatus::OPEN {
+            return Err(MerchantPayError::InvoiceNotOpen(InvoiceNotOpen{}));
+        }
+        invoice.status = InvoiceStatus::VOID;
+        self.invoices.setter(invoice_id).set(invoice);
+
+        evm::log(InvoiceVoided {
+            invoiceId: invoice_id,
+            issuer: caller,
+        });
+        Ok(())
+    }
+}
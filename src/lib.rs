@@ -1,20 +1,51 @@
 #![cfg_attr(not(feature = "export-abi"), no_main)]
 extern crate alloc;
 
+mod eip712;
+pub mod erc20;
+mod fees;
+mod invoices;
+mod offers;
+mod pausable;
+mod reentrancy;
+mod safe_erc20;
+mod sets;
+mod subscriptions;
+mod upgrades;
 
 use stylus_sdk::{
     alloy_primitives::{Address, U256, B256},
+    block,
     contract,
     evm,
     msg,
     prelude::*,
-    call::{Call, call},
+    call::{Call, call, transfer_eth},
 };
 use alloy_sol_types::sol;
 
+use crate::fees::{FeeSchedule, DEFAULT_FEE_BPS};
+use crate::invoices::{Invoice, Invoices};
+use crate::offers::{Offer, SignedOffers, DOMAIN_NAME, DOMAIN_VERSION};
+use crate::pausable::{Pausable, PAUSE_ALL, PAUSE_LISTINGS, PAUSE_PAYMENTS};
+use crate::reentrancy::ReentrancyGuard;
+use crate::sets::Bytes32Set;
+use crate::subscriptions::{Plan, Subscription, Subscriptions};
+use crate::upgrades::Upgrades;
+
+/// Token address used for listings priced in the chain's native asset
+pub const NATIVE_TOKEN: Address = Address::ZERO;
+
+/// Upper bound on the number of entries a paginated view scans per call
+pub const MAX_PAGE_SIZE: u64 = 100;
+
 sol_interface! {
     interface IERC20 {
         function transferFrom(address from, address to, uint256 amount) external returns (bool);
+        function transfer(address to, uint256 amount) external returns (bool);
+        function allowance(address owner, address spender) external view returns (uint256);
+        function balanceOf(address account) external view returns (uint256);
+        function permit(address owner, address spender, uint256 value, uint256 deadline, uint8 v, bytes32 r, bytes32 s) external;
     }
 }
 
@@ -23,26 +54,159 @@ sol! {
     event NewListing(
         bytes32 indexed id,
         address indexed seller,
+        address indexed token,
         uint256 rate,
-        uint256 quantity
+        uint256 quantity,
+        bytes32 metadata
+    );
+
+    event ListingUpdated(
+        bytes32 indexed id,
+        address indexed seller,
+        uint256 oldRate,
+        uint256 newRate,
+        uint256 oldQuantity,
+        uint256 newQuantity,
+        bytes32 oldMetadata,
+        bytes32 newMetadata
     );
 
     event ListingPaid(
         bytes32 indexed id,
         address indexed seller,
         address indexed buyer,
-        uint256 amount,
+        uint256 orderId,
+        uint256 price,
+        uint256 tip,
+        uint256 fee,
         uint256 quantity
     );
 
+    event EscrowCreated(
+        uint256 indexed orderId,
+        bytes32 indexed id,
+        address indexed buyer,
+        address seller,
+        uint256 amount
+    );
+
+    event EscrowReleased(
+        uint256 indexed orderId,
+        bytes32 indexed id,
+        address indexed seller,
+        address buyer,
+        uint256 amount
+    );
+
+    event EscrowRefunded(
+        uint256 indexed orderId,
+        bytes32 indexed id,
+        address indexed buyer,
+        address seller,
+        uint256 amount
+    );
+
+    event ListingCancelled(
+        bytes32 indexed id,
+        address indexed seller
+    );
+
+    event ListingRemoved(
+        bytes32 indexed id,
+        address indexed seller
+    );
+
+    event SaleWindowUpdated(
+        bytes32 indexed id,
+        address indexed seller,
+        uint256 startsAt,
+        uint256 endsAt
+    );
+
+    event PriceChangeScheduled(
+        bytes32 indexed id,
+        address indexed seller,
+        uint256 rate,
+        uint256 startsAt,
+        uint256 endsAt
+    );
+
+    event FeeCollected(
+        address indexed token,
+        bytes32 indexed id,
+        uint8 source,
+        uint256 amount
+    );
+
+    event FeesWithdrawn(
+        address indexed token,
+        address indexed to,
+        address indexed caller,
+        uint256 amount
+    );
+
+    event TreasuryUpdated(
+        address indexed previousTreasury,
+        address indexed newTreasury
+    );
+
+    event PaymentTokenUpdated(
+        address indexed token,
+        bool accepted
+    );
+
+    event FeeOnTransferUpdated(
+        address indexed token,
+        bool flagged
+    );
+
+    event OwnershipTransferStarted(
+        address indexed previousOwner,
+        address indexed newOwner
+    );
+
+    event OwnershipTransferred(
+        address indexed previousOwner,
+        address indexed newOwner
+    );
+
     // Define errors
     error InvalidListing();
     error InvalidQuantity();
     error InvalidAmount();
     error InvalidSeller();
-    error TransferFailed();
+    error TransferFailed(address token, bytes reason);
     error ListingNotFound();
     error Unauthorized();
+    error OrderNotFound();
+    error EscrowNotHeld();
+    error ListingIsCancelled();
+    error AlreadyInitialized();
+    error InsufficientFees();
+    error InvalidFee();
+    error TokenNotAccepted();
+    error WrongPaymentMethod();
+    error ListingAlreadyExists();
+    error UnsettledOrders();
+    error InvalidSaleWindow();
+    error OutsideSaleWindow();
+    error InvalidPlan();
+    error PlanNotFound();
+    error SubscriptionNotFound();
+    error SubscriptionInactive();
+    error ChargeNotDue();
+    error InvoiceNotFound();
+    error InvoiceNotOpen();
+    error OfferExpired();
+    error OfferCancelled();
+    error InvalidSignature();
+    error PermitFailed();
+    error ReentrantCall();
+    error ShortDelivery(address token, uint256 expected, uint256 received);
+    error ContractPaused(uint8 flags);
+    error InvalidPauseFlags();
+    error InvalidImplementation();
+    error ListingNotCancelled();
 }
 
 // Define Status enum
@@ -55,15 +219,94 @@ pub enum Status {
     CANCELLED,
 }
 
-// Define Listing struct
+// Define Listing struct. Listings persist across upgrades, so new fields may only be appended
 #[derive(Default, Clone, StorageType)]
 pub struct Listing {
     id: B256,
     seller: Address,
-    buyer: Address,
+    token: Address,
     rate: U256,
     quantity: U256,
     status: Status,
+    escrow: bool,
+    open_escrows: U256,
+    metadata: B256,
+    starts_at: U256,
+    ends_at: U256,
+    sale_rate: U256,
+    sale_starts_at: U256,
+    sale_ends_at: U256,
+    /// When the listing was added; zero for listings from before storage version 2
+    listed_at: U256,
+}
+
+// Timestamps of zero leave the corresponding end of a window open
+impl Listing {
+    /// Whether `now` falls inside the listing's sale window
+    fn is_open_at(&self, now: U256) -> bool {
+        if now < self.starts_at {
+            return false;
+        }
+        self.ends_at == U256::ZERO || now < self.ends_at
+    }
+
+    /// Unit price at `now`, taking any scheduled price change into account
+    fn rate_at(&self, now: U256) -> U256 {
+        if self.sale_rate == U256::ZERO || now < self.sale_starts_at {
+            return self.rate;
+        }
+        if self.sale_ends_at != U256::ZERO && now >= self.sale_ends_at {
+            return self.rate;
+        }
+        self.sale_rate
+    }
+}
+
+// Define OrderStatus enum
+#[derive(Default, Clone, Copy, PartialEq, Eq, StorageType)]
+pub enum OrderStatus {
+    #[default]
+    ESCROWED,
+    SETTLED,
+    REFUNDED,
+}
+
+// Define Order struct, one per purchase
+#[derive(Default, Clone, StorageType)]
+pub struct Order {
+    id: U256,
+    listing_id: B256,
+    seller: Address,
+    buyer: Address,
+    token: Address,
+    quantity: U256,
+    rate: U256,
+    amount: U256,
+    tip: U256,
+    fee: U256,
+    timestamp: U256,
+    status: OrderStatus,
+}
+
+// How a purchase is funded; either way the buyer spends exactly price + tip
+enum Payment {
+    // ERC-20 payment that fails if price + tip exceeds `max_amount`
+    Token { max_amount: U256 },
+    // Native payment of msg::value(), which bounds price + tip; the rest is refunded
+    Native,
+}
+
+// What a collected fee was charged on, telling apart the ids in FeeCollected
+#[derive(Clone, Copy)]
+enum FeeSource {
+    // `id` is the listing id
+    Listing = 0,
+    // `id` is the listing id the signed offer was made for
+    Offer = 1,
+    // `id` is the subscription id
+    Subscription = 2,
+    // `id` is the invoice id
+    Invoice = 3,
 }
 
 // Define storage
@@ -72,8 +315,28 @@ sol_storage! {
     pub struct MerchantPay {
         address USDC;
         mapping(bytes32 => mapping(address => Listing)) listings;
-        bytes32[] listing_keys;
-        mapping(address => bytes32[]) address_to_listing;
+        Bytes32Set listing_keys;
+        mapping(address => Bytes32Set) address_to_listing;
+        mapping(bytes32 => uint256) listing_key_refs;
+        uint256 order_count;
+        mapping(uint256 => Order) orders;
+        mapping(bytes32 => mapping(address => uint256[])) escrow_orders;
+        mapping(address => uint256[]) buyer_orders;
+        bool initialized;
+        address owner;
+        address pending_owner;
+        address treasury;
+        mapping(address => uint256) collected_fees;
+        FeeSchedule fees;
+        mapping(address => bool) accepted_tokens;
+        Subscriptions subscriptions;
+        Invoices invoices;
+        SignedOffers offers;
+        ReentrancyGuard reentrancy;
+        mapping(address => bool) fee_on_transfer_tokens;
+        Pausable pausable;
+        Upgrades upgrades;
+        mapping(uint256 => uint256) escrow_order_position;
     }
 }
 
@@ -87,159 +350,1474 @@ pub enum MerchantPayError {
     TransferFailed(TransferFailed),
     ListingNotFound(ListingNotFound),
     Unauthorized(Unauthorized),
+    OrderNotFound(OrderNotFound),
+    EscrowNotHeld(EscrowNotHeld),
+    ListingIsCancelled(ListingIsCancelled),
+    AlreadyInitialized(AlreadyInitialized),
+    InsufficientFees(InsufficientFees),
+    InvalidFee(InvalidFee),
+    TokenNotAccepted(TokenNotAccepted),
+    WrongPaymentMethod(WrongPaymentMethod),
+    ListingAlreadyExists(ListingAlreadyExists),
+    UnsettledOrders(UnsettledOrders),
+    InvalidSaleWindow(InvalidSaleWindow),
+    OutsideSaleWindow(OutsideSaleWindow),
+    InvalidPlan(InvalidPlan),
+    PlanNotFound(PlanNotFound),
+    SubscriptionNotFound(SubscriptionNotFound),
+    SubscriptionInactive(SubscriptionInactive),
+    ChargeNotDue(ChargeNotDue),
+    InvoiceNotFound(InvoiceNotFound),
+    InvoiceNotOpen(InvoiceNotOpen),
+    OfferExpired(OfferExpired),
+    OfferCancelled(OfferCancelled),
+    InvalidSignature(InvalidSignature),
+    PermitFailed(PermitFailed),
+    ReentrantCall(ReentrantCall),
+    ShortDelivery(ShortDelivery),
+    ContractPaused(ContractPaused),
+    InvalidPauseFlags(InvalidPauseFlags),
+    InvalidImplementation(InvalidImplementation),
+    ListingNotCancelled(ListingNotCancelled),
 }
 
-#[public]
+// Internal helpers, not exposed to other contracts
 impl MerchantPay {
-    pub fn initialize(&mut self, usdc: Address) -> Result<(), MerchantPayError> {
-        self.USDC.set(usdc);
+    /// Fails unless msg::sender() is the contract owner
+    fn only_owner(&self) -> Result<(), MerchantPayError> {
+        if msg::sender() != self.owner.get() {
+            return Err(MerchantPayError::Unauthorized(Unauthorized{}));
+        }
         Ok(())
     }
 
-    pub fn add_listing(&mut self, id: B256, rate: U256, quantity: U256) -> Result<(), MerchantPayError> {
-        if rate == U256::ZERO || quantity == U256::ZERO {
-            return Err(MerchantPayError::InvalidAmount(InvalidAmount{}));
+    /// Data migrations for each storage version after `from`, applied in order. Version 1 only
+    /// introduced versioning and version 2 appends a `Listing` field that reads as zero for
+    /// older listings, so neither has data to move.
+    fn migrate_storage(&mut self, _from: U256) {}
+
+    /// Fails unless msg::sender() is the pauser or the contract owner
+    fn only_pauser_or_owner(&self) -> Result<(), MerchantPayError> {
+        let caller = msg::sender();
+        if caller != self.pausable.pauser() && caller != self.owner.get() {
+            return Err(MerchantPayError::Unauthorized(Unauthorized{}));
         }
+        Ok(())
+    }
 
-        let listing = Listing {
-            id,
-            seller: msg::sender(),
-            buyer: Address::ZERO,
-            rate,
-            quantity,
-            status: Status::PENDING,
-        };
+    /// Moves `amount` of `token` from `from` to `to` using the caller's allowance
+    fn token_transfer_from(&mut self, token: Address, from: Address, to: Address, amount: U256) -> Result<(), MerchantPayError> {
+        safe_erc20::safe_transfer_from(self, token, from, to, amount)
+    }
 
-        // Store listing
-        let mut seller_listings = self.listings.setter(id);
-        seller_listings.setter(msg::sender()).set(listing.clone());
+    /// Moves `amount` of `token` held by this contract to `to`
+    fn token_transfer(&mut self, token: Address, to: Address, amount: U256) -> Result<(), MerchantPayError> {
+        if token == NATIVE_TOKEN {
+            return transfer_eth(to, amount).map_err(|reason| safe_erc20::transfer_failed(token, reason));
+        }
+        safe_erc20::safe_transfer(self, token, to, amount)
+    }
 
-        // Add to listing_keys if new
-        let mut is_new_bytes_key = true;
-        for i in 0..self.listing_keys.len() {
-            if self.listing_keys.get(i) == Some(&id) {
-                is_new_bytes_key = false;
-                break;
-            }
+    /// Grants this contract an allowance of `value` over `owner`'s `token` using an ERC-2612 permit.
+    /// A permit that fails is accepted as long as the allowance is already in place, since it
+    /// may have been submitted (or front-run) in an earlier transaction.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_permit(
+        &mut self,
+        token: Address,
+        owner: Address,
+        value: U256,
+        deadline: U256,
+        v: u8,
+        r: B256,
+        s: B256
+    ) -> Result<(), MerchantPayError> {
+        let erc20 = IERC20::new(token);
+        let spender = contract::address();
+        let config = Call::new_in(self);
+        if erc20.permit(config, owner, spender, value, deadline, v, r, s).is_ok() {
+            return Ok(());
         }
-        if is_new_bytes_key {
-            self.listing_keys.push(id);
+
+        let config = Call::new_in(self);
+        match erc20.allowance(config, owner, spender) {
+            Ok(allowance) if allowance >= value => Ok(()),
+            _ => Err(MerchantPayError::PermitFailed(PermitFailed{})),
         }
+    }
 
-        // Add to address_to_listing if new
-        let mut is_new_address_key = true;
-        let bytes_keys = self.address_to_listing.getter(msg::sender());
-        for i in 0..bytes_keys.len() {
-            if bytes_keys.get(i) == Some(&id) {
-                is_new_address_key = false;
-                break;
-            }
+    /// This contract's balance of the ERC-20 `token`
+    fn token_balance(&mut self, token: Address) -> Result<U256, MerchantPayError> {
+        let erc20 = IERC20::new(token);
+        let config = Call::new_in(self);
+        erc20
+            .balance_of(config, contract::address())
+            .map_err(|err| safe_erc20::transfer_failed(token, err.into()))
+    }
+
+    /// Pulls a payment of `amount` from `from` into the contract and returns how much arrived.
+    /// Native payments are already held as msg::value(). Tokens flagged as fee-on-transfer
+    /// may deliver less than `amount`; a shortfall from any other token is rejected.
+    fn pull_payment(&mut self, token: Address, from: Address, amount: U256) -> Result<U256, MerchantPayError> {
+        if token == NATIVE_TOKEN {
+            return Ok(amount);
+        }
+
+        let before = self.token_balance(token)?;
+        self.token_transfer_from(token, from, contract::address(), amount)?;
+        self.received_since(token, before, amount)
+    }
+
+    /// How much of an `expected` transfer of `token` arrived since the balance was `before`
+    fn received_since(&mut self, token: Address, before: U256, expected: U256) -> Result<U256, MerchantPayError> {
+        let received = self.token_balance(token)?.saturating_sub(before);
+        if received >= expected {
+            return Ok(expected);
         }
-        if is_new_address_key {
-            self.address_to_listing.setter(msg::sender()).push(id);
+        if !self.fee_on_transfer_tokens.get(token) {
+            return Err(MerchantPayError::ShortDelivery(ShortDelivery {
+                token,
+                expected,
+                received,
+            }));
         }
+        Ok(received)
+    }
 
-        // Emit event
-        evm::log(NewListing {
-            id,
-            seller: msg::sender(),
-            rate,
-            quantity,
-        });
-        Ok(())
+    /// Part of a received payment left after the platform fee; fails if the fee is not covered
+    fn net_of_fee(token: Address, expected: U256, received: U256, fee: U256) -> Result<U256, MerchantPayError> {
+        if received < fee {
+            return Err(MerchantPayError::ShortDelivery(ShortDelivery {
+                token,
+                expected,
+                received,
+            }));
+        }
+        Ok(received - fee)
     }
 
-    pub fn pay_for_listing(
-        &mut self, 
-        id: B256, 
-        seller: Address, 
-        quantity: U256, 
-        amount: U256
-    ) -> Result<(), MerchantPayError> {
-        let mut listing_map = self.listings.setter(id);
-        let mut listing = listing_map.getter(seller).get();
+    /// Shared purchase flow for token and native payments
+    fn purchase(
+        &mut self,
+        id: B256,
+        seller: Address,
+        quantity: U256,
+        tip: U256,
+        payment: Payment
+    ) -> Result<U256, MerchantPayError> {
+        let mut listing = self.listings.getter(id).getter(seller).get();
 
         // Validate listing
+        if listing.seller == Address::ZERO {
+            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        }
+
+        if listing.status == Status::CANCELLED {
+            return Err(MerchantPayError::ListingIsCancelled(ListingIsCancelled{}));
+        }
+
         if listing.status != Status::PENDING && listing.status != Status::PAID {
             return Err(MerchantPayError::InvalidListing(InvalidListing{}));
         }
         
-        if quantity > listing.quantity {
+        if quantity == U256::ZERO || quantity > listing.quantity {
             return Err(MerchantPayError::InvalidQuantity(InvalidQuantity{}));
         }
 
-        let price = listing.rate * quantity;
-        if amount < price {
+        let now = U256::from(block::timestamp());
+        if !listing.is_open_at(now) {
+            return Err(MerchantPayError::OutsideSaleWindow(OutsideSaleWindow{}));
+        }
+
+        let rate = listing.rate_at(now);
+        let price = rate
+            .checked_mul(quantity)
+            .ok_or(MerchantPayError::InvalidAmount(InvalidAmount{}))?;
+
+        // A tip that overflows the total would otherwise let the buyer pay nothing
+        let total = price
+            .checked_add(tip)
+            .ok_or(MerchantPayError::InvalidAmount(InvalidAmount{}))?;
+
+        // The price may have moved since the buyer looked, so the total is checked against
+        // what they agreed to spend; native payments refund whatever is left over
+        let native = matches!(payment, Payment::Native);
+        let max_amount = match payment {
+            Payment::Token { max_amount } => max_amount,
+            Payment::Native => msg::value(),
+        };
+        if total > max_amount {
             return Err(MerchantPayError::InvalidAmount(InvalidAmount{}));
         }
+        let refund = if native { max_amount - total } else { U256::ZERO };
+
+        // Payments settle in the listing's own token, which must still be accepted
+        let token = listing.token;
+        if !self.accepted_tokens.get(token) {
+            return Err(MerchantPayError::TokenNotAccepted(TokenNotAccepted{}));
+        }
+        if native != (token == NATIVE_TOKEN) {
+            return Err(MerchantPayError::WrongPaymentMethod(WrongPaymentMethod{}));
+        }
 
-        // Calculate charge
-        let charge = self.deduct_charge(listing.rate);
+        // The charge covers the tip too, so moving price into tips cannot avoid it
+        let charge = self.fees.fee_for(seller, token, total)?;
 
-        // Transfer tokens
-        let erc20 = IERC20::new(*self.USDC);
-        let config = Call::new_in(self);
-        
-        // Transfer to seller
-        if erc20.transfer_from(config, msg::sender(), seller, price - charge).is_err() {
-            return Err(MerchantPayError::TransferFailed(TransferFailed{}));
+        let order_id = self.next_order_id();
+        let mut order = Order {
+            id: order_id,
+            listing_id: id,
+            seller,
+            buyer: msg::sender(),
+            token,
+            quantity,
+            rate,
+            amount: price,
+            tip,
+            fee: charge,
+            timestamp: now,
+            status: OrderStatus::SETTLED,
+        };
+
+        if listing.escrow {
+            order.status = OrderStatus::ESCROWED;
+            listing.open_escrows += U256::from(1);
         }
-        
-        // Transfer charge
-        if erc20.transfer_from(config, msg::sender(), contract::address(), charge).is_err() {
-            return Err(MerchantPayError::TransferFailed(TransferFailed{}));
+
+        // Record the order and update the listing before any funds move
+        self.record_order(order);
+        if listing.escrow {
+            self.track_escrow(id, seller, order_id);
         }
 
-        // Update listing
-        listing.buyer = msg::sender();
         listing.quantity -= quantity;
-        listing.status = if listing.quantity == U256::ZERO {
+        listing.status = if listing.quantity == U256::ZERO && listing.open_escrows == U256::ZERO {
             Status::COMPLETED
         } else {
             Status::PAID
         };
 
-        listing_map.setter(seller).set(listing.clone());
+        self.listings.setter(id).setter(seller).set(listing.clone());
+
+        if listing.escrow {
+            // Hold the full price and tip until the buyer confirms delivery
+            let received = self.pull_payment(token, msg::sender(), total)?;
+            let held = Self::net_of_fee(token, total, received, charge)?;
+
+            // A fee-on-transfer token delivered less; the seller absorbs the difference, out of
+            // the tip first, so the order's amount and tip add up to what actually arrived
+            if received < total {
+                let mut order = self.orders.getter(order_id).get();
+                let shortfall = total - received;
+                let from_tip = shortfall.min(order.tip);
+                order.tip -= from_tip;
+                order.amount -= shortfall - from_tip;
+                self.orders.setter(order_id).set(order);
+            }
+
+            evm::log(EscrowCreated {
+                orderId: order_id,
+                id,
+                buyer: msg::sender(),
+                seller,
+                amount: held,
+            });
+        } else {
+            self.pay_direct(token, msg::sender(), seller, total, charge, id, FeeSource::Listing)?;
+        }
+
+        // Return any excess ETH
+        if refund > U256::ZERO {
+            self.token_transfer(NATIVE_TOKEN, msg::sender(), refund)?;
+        }
 
         evm::log(ListingPaid {
             id,
             seller,
             buyer: msg::sender(),
-            amount,
+            orderId: order_id,
+            price,
+            tip,
+            fee: charge,
             quantity,
         });
-        Ok(())
+        Ok(order_id)
     }
 
-    pub fn get_listing(&self, id: B256, seller: Address) -> Result<Listing, MerchantPayError> {
-        let listing = self.listings.getter(id).getter(seller).get();
-        if listing.seller == Address::ZERO {
-            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+    /// Resolves `cursor` and `limit` into the `[start, end)` range of an index holding `len` entries
+    fn page_bounds(len: usize, cursor: U256, limit: U256) -> (usize, usize) {
+        if cursor >= U256::from(len) {
+            return (len, len);
         }
-        Ok(listing)
+        let start = cursor.to::<usize>();
+        // A zero limit would never advance the cursor, so it asks for a full page instead
+        let limit = if limit == U256::ZERO { U256::from(MAX_PAGE_SIZE) } else { limit };
+        let limit = limit.min(U256::from(MAX_PAGE_SIZE)).to::<usize>();
+        (start, (start + limit).min(len))
     }
 
-    pub fn get_all_listings_for_address(&self, seller: Address) -> Result<Vec<Listing>, MerchantPayError> {
-        let mut listings = Vec::new();
-        let bytes_keys = self.address_to_listing.getter(seller);
-        
-        for i in 0..bytes_keys.len() {
-            if let Some(id) = bytes_keys.get(i) {
-                let listing = self.listings.getter(*id).getter(seller).get();
-                if listing.seller != Address::ZERO {
-                    listings.push(listing);
+    /// Cursor to hand back after scanning up to `end`; zero once the index is exhausted
+    fn next_cursor(len: usize, end: usize) -> U256 {
+        if end >= len {
+            return U256::ZERO;
+        }
+        U256::from(end)
+    }
+
+    /// Reserves the next order id
+    fn next_order_id(&mut self) -> U256 {
+        let order_id = self.order_count.get() + U256::from(1);
+        self.order_count.set(order_id);
+        order_id
+    }
+
+    /// Stores `order` and adds it to its buyer's purchase history
+    fn record_order(&mut self, order: Order) {
+        self.buyer_orders.setter(order.buyer).push(order.id);
+        self.orders.setter(order.id).set(order);
+    }
+
+    /// Adds an escrowed order to its listing's index of open escrows
+    fn track_escrow(&mut self, id: B256, seller: Address, order_id: U256) {
+        let mut listing_escrows = self.escrow_orders.setter(id);
+        let mut escrows = listing_escrows.setter(seller);
+        let position = U256::from(escrows.len());
+        escrows.push(order_id);
+        self.escrow_order_position.insert(order_id, position);
+    }
+
+    /// Drops a settled order from its listing's index of open escrows
+    fn untrack_escrow(&mut self, id: B256, seller: Address, order_id: U256) {
+        let position = self.escrow_order_position.get(order_id);
+        let mut listing_escrows = self.escrow_orders.setter(id);
+        let mut escrows = listing_escrows.setter(seller);
+
+        // Move the last entry into the settled order's slot
+        let last_position = escrows.len() - 1;
+        let mut moved = None;
+        if position != U256::from(last_position) {
+            if let Some(last) = escrows.get(last_position) {
+                if let Some(mut slot) = escrows.setter(position) {
+                    slot.set(last);
                 }
+                moved = Some(last);
             }
         }
-        
-        if listings.is_empty() {
-            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        escrows.pop();
+
+        if let Some(last) = moved {
+            self.escrow_order_position.insert(last, position);
         }
-        
-        Ok(listings)
+        self.escrow_order_position.delete(order_id);
+    }
+
+    /// Settles a payment of `amount` straight to `payee`, keeping `fee` for the platform
+    #[allow(clippy::too_many_arguments)]
+    fn pay_direct(
+        &mut self,
+        token: Address,
+        payer: Address,
+        payee: Address,
+        amount: U256,
+        fee: U256,
+        id: B256,
+        source: FeeSource
+    ) -> Result<(), MerchantPayError> {
+        self.accrue_fee(token, id, source, fee);
+
+        // Pull the whole payment in first so what actually arrived can be measured
+        let received = self.pull_payment(token, payer, amount)?;
+        let payout = Self::net_of_fee(token, amount, received, fee)?;
+        self.token_transfer(token, payee, payout)
+    }
+
+    /// Pays out one period of `plan` of which `received` was pulled into the contract, keeping `fee`
+    fn settle_subscription_charge(
+        &mut self,
+        plan: &Plan,
+        subscription_id: U256,
+        fee: U256,
+        received: U256
+    ) -> Result<(), MerchantPayError> {
+        let payout = Self::net_of_fee(plan.token(), plan.amount(), received, fee)?;
+        let id = B256::from(subscription_id.to_be_bytes::<32>());
+        self.accrue_fee(plan.token(), id, FeeSource::Subscription, fee);
+        self.token_transfer(plan.token(), plan.merchant(), payout)
+    }
+
+    /// Books `amount` of `token` as platform revenue from `id`, which `source` says how to read
+    fn accrue_fee(&mut self, token: Address, id: B256, source: FeeSource, amount: U256) {
+        if amount == U256::ZERO {
+            return;
+        }
+        let mut collected = self.collected_fees.setter(token);
+        let total = collected.get() + amount;
+        collected.set(total);
+
+        evm::log(FeeCollected {
+            token,
+            id,
+            source: source as u8,
+            amount,
+        });
+    }
+}
+
+#[public]
+impl MerchantPay {
+    /// One-shot setup; the caller becomes the owner and `usdc` the first accepted token
+    pub fn initialize(&mut self, usdc: Address) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        if self.initialized.get() {
+            return Err(MerchantPayError::AlreadyInitialized(AlreadyInitialized{}));
+        }
+        self.initialized.set(true);
+        self.USDC.set(usdc);
+        self.owner.set(msg::sender());
+        self.fees.set_rate(U256::from(DEFAULT_FEE_BPS))?;
+        self.accepted_tokens.insert(usdc, true);
+        self.upgrades.init();
+
+        evm::log(PaymentTokenUpdated {
+            token: usdc,
+            accepted: true,
+        });
+
+        evm::log(OwnershipTransferred {
+            previousOwner: Address::ZERO,
+            newOwner: msg::sender(),
+        });
+        Ok(())
+    }
+
+    pub fn owner(&self) -> Address {
+        self.owner.get()
+    }
+
+    pub fn pending_owner(&self) -> Address {
+        self.pending_owner.get()
+    }
+
+    /// Implementation the ERC-1967 proxy currently delegates to
+    pub fn implementation(&self) -> Address {
+        self.upgrades.implementation()
+    }
+
+    /// Points the proxy at a new implementation. The proxy runs the new `migrate` right after.
+    pub fn upgrade_to(&mut self, implementation: Address) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        self.upgrades.upgrade_to(implementation)
+    }
+
+    pub fn storage_version(&self) -> U256 {
+        self.upgrades.storage_version()
+    }
+
+    /// Brings stored data up to this implementation's storage version. The proxy calls it after
+    /// `upgrade_to`; each version's migration runs once and later calls do nothing.
+    pub fn migrate(&mut self) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        if let Some(from) = self.upgrades.begin_migration() {
+            self.migrate_storage(from);
+        }
+        Ok(())
+    }
+
+    pub fn pauser(&self) -> Address {
+        self.pausable.pauser()
+    }
+
+    /// Bitmask of paused areas: 1 for payments, 2 for listings
+    pub fn paused(&self) -> u8 {
+        self.pausable.paused()
+    }
+
+    /// Grants the pauser role, replacing the current pauser
+    pub fn set_pauser(&mut self, pauser: Address) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        self.pausable.set_pauser(pauser);
+        Ok(())
+    }
+
+    /// Pauses payments (1), listings (2) or both (3); callable by the pauser or the owner
+    pub fn pause(&mut self, flags: u8) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_pauser_or_owner()?;
+        self.pausable.pause(msg::sender(), flags)
     }
 
-    fn deduct_charge(&self, amount: U256) -> U256 {
-        amount / U256::from(1000) // 0.1%
+    /// Resumes the areas in `flags`; callable by the pauser or the owner
+    pub fn unpause(&mut self, flags: u8) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_pauser_or_owner()?;
+        self.pausable.unpause(msg::sender(), flags)
+    }
+
+    /// Starts a two-step ownership transfer; `new_owner` must call `accept_ownership` to complete it
+    pub fn transfer_ownership(&mut self, new_owner: Address) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        self.pending_owner.set(new_owner);
+
+        evm::log(OwnershipTransferStarted {
+            previousOwner: self.owner.get(),
+            newOwner: new_owner,
+        });
+        Ok(())
+    }
+
+    pub fn accept_ownership(&mut self) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        let new_owner = msg::sender();
+        if new_owner != self.pending_owner.get() {
+            return Err(MerchantPayError::Unauthorized(Unauthorized{}));
+        }
+        let previous_owner = self.owner.get();
+        self.owner.set(new_owner);
+        self.pending_owner.set(Address::ZERO);
+
+        evm::log(OwnershipTransferred {
+            previousOwner: previous_owner,
+            newOwner: new_owner,
+        });
+        Ok(())
+    }
+
+    /// Creates a new listing; use `update_listing` to change an existing one
+    pub fn add_listing(
+        &mut self,
+        id: B256,
+        token: Address,
+        rate: U256,
+        quantity: U256,
+        escrow: bool,
+        metadata: B256
+    ) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.pausable.when_not_paused(PAUSE_LISTINGS)?;
+        if rate == U256::ZERO || quantity == U256::ZERO {
+            return Err(MerchantPayError::InvalidAmount(InvalidAmount{}));
+        }
+
+        if self.address_to_listing.getter(msg::sender()).contains(id) {
+            return Err(MerchantPayError::ListingAlreadyExists(ListingAlreadyExists{}));
+        }
+
+        if !self.accepted_tokens.get(token) {
+            return Err(MerchantPayError::TokenNotAccepted(TokenNotAccepted{}));
+        }
+
+        let listing = Listing {
+            id,
+            seller: msg::sender(),
+            token,
+            rate,
+            quantity,
+            status: Status::PENDING,
+            escrow,
+            open_escrows: U256::ZERO,
+            metadata,
+            listed_at: U256::from(block::timestamp()),
+            ..Default::default()
+        };
+
+        // Store listing
+        let mut seller_listings = self.listings.setter(id);
+        seller_listings.setter(msg::sender()).set(listing.clone());
+
+        // Index the listing; the global set is shared between sellers and may already hold the id
+        self.listing_keys.insert(id);
+        self.address_to_listing.setter(msg::sender()).insert(id);
+        let refs = self.listing_key_refs.get(id);
+        self.listing_key_refs.insert(id, refs + U256::from(1));
+
+        // Emit event
+        evm::log(NewListing {
+            id,
+            seller: msg::sender(),
+            token,
+            rate,
+            quantity,
+            metadata,
+        });
+        Ok(())
+    }
+
+    /// Changes the price, remaining stock and metadata of one of the caller's listings,
+    /// leaving its sale state untouched
+    pub fn update_listing(
+        &mut self,
+        id: B256,
+        rate: U256,
+        quantity: U256,
+        metadata: B256
+    ) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.pausable.when_not_paused(PAUSE_LISTINGS)?;
+        let seller = msg::sender();
+        let mut listing = self.listings.getter(id).getter(seller).get();
+        if listing.seller == Address::ZERO {
+            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        }
+        if listing.status == Status::CANCELLED || listing.status == Status::COMPLETED {
+            return Err(MerchantPayError::InvalidListing(InvalidListing{}));
+        }
+        if rate == U256::ZERO || quantity == U256::ZERO {
+            return Err(MerchantPayError::InvalidAmount(InvalidAmount{}));
+        }
+
+        let event = ListingUpdated {
+            id,
+            seller,
+            oldRate: listing.rate,
+            newRate: rate,
+            oldQuantity: listing.quantity,
+            newQuantity: quantity,
+            oldMetadata: listing.metadata,
+            newMetadata: metadata,
+        };
+
+        listing.rate = rate;
+        listing.quantity = quantity;
+        listing.metadata = metadata;
+        self.listings.setter(id).setter(seller).set(listing);
+
+        evm::log(event);
+        Ok(())
+    }
+
+    /// Pays for `quantity` units of a listing plus `tip` for the seller. The platform fee is
+    /// charged on price and tip together. `max_amount` bounds the total, so a price change
+    /// landing first makes the purchase fail rather than spend more than the buyer expected.
+    pub fn pay_for_listing(
+        &mut self, 
+        id: B256, 
+        seller: Address, 
+        quantity: U256, 
+        tip: U256,
+        max_amount: U256
+    ) -> Result<U256, MerchantPayError> {
+        self.pausable.when_not_paused(PAUSE_PAYMENTS)?;
+        self.reentrancy.enter()?;
+        let order_id = self.purchase(id, seller, quantity, tip, Payment::Token { max_amount })?;
+        self.reentrancy.exit();
+        Ok(order_id)
+    }
+
+    /// Pays for a listing in a single transaction by submitting an ERC-2612 permit for
+    /// `max_amount` alongside the payment, instead of a separate `approve`
+    #[allow(clippy::too_many_arguments)]
+    pub fn pay_for_listing_with_permit(
+        &mut self,
+        id: B256,
+        seller: Address,
+        quantity: U256,
+        tip: U256,
+        max_amount: U256,
+        deadline: U256,
+        v: u8,
+        r: B256,
+        s: B256
+    ) -> Result<U256, MerchantPayError> {
+        self.pausable.when_not_paused(PAUSE_PAYMENTS)?;
+        let listing = self.listings.getter(id).getter(seller).get();
+        if listing.seller == Address::ZERO {
+            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        }
+        if listing.token == NATIVE_TOKEN {
+            return Err(MerchantPayError::WrongPaymentMethod(WrongPaymentMethod{}));
+        }
+
+        self.reentrancy.enter()?;
+        self.apply_permit(listing.token, msg::sender(), max_amount, deadline, v, r, s)?;
+        let order_id = self.purchase(id, seller, quantity, tip, Payment::Token { max_amount })?;
+        self.reentrancy.exit();
+        Ok(order_id)
+    }
+
+    /// Pays for a listing priced in the native asset, like `pay_for_listing` with msg::value()
+    /// as the spending bound. Anything sent beyond price and `tip` is refunded.
+    #[payable]
+    pub fn pay_for_listing_native(
+        &mut self,
+        id: B256,
+        seller: Address,
+        quantity: U256,
+        tip: U256
+    ) -> Result<U256, MerchantPayError> {
+        self.pausable.when_not_paused(PAUSE_PAYMENTS)?;
+        self.reentrancy.enter()?;
+        let order_id = self.purchase(id, seller, quantity, tip, Payment::Native)?;
+        self.reentrancy.exit();
+        Ok(order_id)
+    }
+
+    /// Called by the buyer once goods have arrived; releases the escrowed funds to the seller
+    pub fn confirm_delivery(&mut self, order_id: U256) -> Result<(), MerchantPayError> {
+        self.pausable.when_not_paused(PAUSE_PAYMENTS)?;
+        let mut order = self.orders.getter(order_id).get();
+        if order.buyer == Address::ZERO {
+            return Err(MerchantPayError::OrderNotFound(OrderNotFound{}));
+        }
+        if order.buyer != msg::sender() {
+            return Err(MerchantPayError::Unauthorized(Unauthorized{}));
+        }
+        if order.status != OrderStatus::ESCROWED {
+            return Err(MerchantPayError::EscrowNotHeld(EscrowNotHeld{}));
+        }
+        // Escrows on a cancelled listing can only be refunded
+        let mut listing = self.listings.getter(order.listing_id).getter(order.seller).get();
+        if listing.status == Status::CANCELLED {
+            return Err(MerchantPayError::ListingIsCancelled(ListingIsCancelled{}));
+        }
+
+        self.reentrancy.enter()?;
+
+        order.status = OrderStatus::SETTLED;
+        self.orders.setter(order_id).set(order.clone());
+        self.untrack_escrow(order.listing_id, order.seller, order_id);
+
+        // A sold-out listing is only completed once every escrow has settled
+        listing.open_escrows -= U256::from(1);
+        if listing.quantity == U256::ZERO && listing.open_escrows == U256::ZERO {
+            listing.status = Status::COMPLETED;
+        }
+        self.listings.setter(order.listing_id).setter(order.seller).set(listing);
+
+        // The charge only becomes platform revenue once the order settles
+        let payout = order.amount + order.tip - order.fee;
+        self.accrue_fee(order.token, order.listing_id, FeeSource::Listing, order.fee);
+        self.token_transfer(order.token, order.seller, payout)?;
+
+        evm::log(EscrowReleased {
+            orderId: order_id,
+            id: order.listing_id,
+            seller: order.seller,
+            buyer: order.buyer,
+            amount: payout,
+        });
+
+        self.reentrancy.exit();
+        Ok(())
+    }
+
+    /// Takes a listing off sale. Escrowed purchases that have not been released become
+    /// refundable in full, and each buyer claims theirs with `claim_refund`.
+    pub fn cancel_listing(&mut self, id: B256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.pausable.when_not_paused(PAUSE_LISTINGS)?;
+        let seller = msg::sender();
+        let mut listing = self.listings.getter(id).getter(seller).get();
+        if listing.seller == Address::ZERO {
+            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        }
+        if listing.status == Status::CANCELLED || listing.status == Status::COMPLETED {
+            return Err(MerchantPayError::InvalidListing(InvalidListing{}));
+        }
+
+        // Refunds are pulled one order at a time, so a buyer that cannot receive
+        // funds never stops the seller from cancelling
+        listing.status = Status::CANCELLED;
+        self.listings.setter(id).setter(seller).set(listing);
+
+        evm::log(ListingCancelled { id, seller });
+        Ok(())
+    }
+
+    /// Called by the buyer of an escrowed order on a cancelled listing; returns the price and tip
+    pub fn claim_refund(&mut self, order_id: U256) -> Result<(), MerchantPayError> {
+        self.pausable.when_not_paused(PAUSE_PAYMENTS)?;
+        let mut order = self.orders.getter(order_id).get();
+        if order.buyer == Address::ZERO {
+            return Err(MerchantPayError::OrderNotFound(OrderNotFound{}));
+        }
+        if order.buyer != msg::sender() {
+            return Err(MerchantPayError::Unauthorized(Unauthorized{}));
+        }
+        if order.status != OrderStatus::ESCROWED {
+            return Err(MerchantPayError::EscrowNotHeld(EscrowNotHeld{}));
+        }
+        let mut listing = self.listings.getter(order.listing_id).getter(order.seller).get();
+        if listing.status != Status::CANCELLED {
+            return Err(MerchantPayError::ListingNotCancelled(ListingNotCancelled{}));
+        }
+
+        self.reentrancy.enter()?;
+
+        order.status = OrderStatus::REFUNDED;
+        self.orders.setter(order_id).set(order.clone());
+        self.untrack_escrow(order.listing_id, order.seller, order_id);
+
+        // Once every refund is claimed the listing can be removed
+        listing.open_escrows -= U256::from(1);
+        self.listings.setter(order.listing_id).setter(order.seller).set(listing);
+
+        let refund = order.amount + order.tip;
+        self.token_transfer(order.token, order.buyer, refund)?;
+
+        evm::log(EscrowRefunded {
+            orderId: order_id,
+            id: order.listing_id,
+            buyer: order.buyer,
+            seller: order.seller,
+            amount: refund,
+        });
+
+        self.reentrancy.exit();
+        Ok(())
+    }
+
+    /// Limits when one of the caller's listings can be bought; zero leaves that end of the window open
+    pub fn set_sale_window(&mut self, id: B256, starts_at: U256, ends_at: U256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.pausable.when_not_paused(PAUSE_LISTINGS)?;
+        let seller = msg::sender();
+        let mut listing = self.listings.getter(id).getter(seller).get();
+        if listing.seller == Address::ZERO {
+            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        }
+        if listing.status == Status::CANCELLED || listing.status == Status::COMPLETED {
+            return Err(MerchantPayError::InvalidListing(InvalidListing{}));
+        }
+        if ends_at != U256::ZERO && ends_at <= starts_at {
+            return Err(MerchantPayError::InvalidSaleWindow(InvalidSaleWindow{}));
+        }
+
+        listing.starts_at = starts_at;
+        listing.ends_at = ends_at;
+        self.listings.setter(id).setter(seller).set(listing);
+
+        evm::log(SaleWindowUpdated {
+            id,
+            seller,
+            startsAt: starts_at,
+            endsAt: ends_at,
+        });
+        Ok(())
+    }
+
+    /// Schedules `rate` to replace the listing's price between `starts_at` and `ends_at`,
+    /// e.g. for a flash sale. A zero `ends_at` keeps the new price indefinitely and a zero
+    /// `rate` clears the schedule.
+    pub fn schedule_price_change(
+        &mut self,
+        id: B256,
+        rate: U256,
+        starts_at: U256,
+        ends_at: U256
+    ) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.pausable.when_not_paused(PAUSE_LISTINGS)?;
+        let seller = msg::sender();
+        let mut listing = self.listings.getter(id).getter(seller).get();
+        if listing.seller == Address::ZERO {
+            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        }
+        if listing.status == Status::CANCELLED || listing.status == Status::COMPLETED {
+            return Err(MerchantPayError::InvalidListing(InvalidListing{}));
+        }
+        if ends_at != U256::ZERO && ends_at <= starts_at {
+            return Err(MerchantPayError::InvalidSaleWindow(InvalidSaleWindow{}));
+        }
+
+        listing.sale_rate = rate;
+        listing.sale_starts_at = starts_at;
+        listing.sale_ends_at = ends_at;
+        self.listings.setter(id).setter(seller).set(listing);
+
+        evm::log(PriceChangeScheduled {
+            id,
+            seller,
+            rate,
+            startsAt: starts_at,
+            endsAt: ends_at,
+        });
+        Ok(())
+    }
+
+    /// Whether the listing can be bought right now
+    pub fn is_purchasable(&self, id: B256, seller: Address) -> bool {
+        let listing = self.listings.getter(id).getter(seller).get();
+        if listing.seller == Address::ZERO || listing.quantity == U256::ZERO {
+            return false;
+        }
+        if listing.status != Status::PENDING && listing.status != Status::PAID {
+            return false;
+        }
+        listing.is_open_at(U256::from(block::timestamp()))
+            && self.accepted_tokens.get(listing.token)
+            && self.pausable.paused() & PAUSE_PAYMENTS == 0
+    }
+
+    /// Unit price the listing would sell at right now
+    pub fn get_current_rate(&self, id: B256, seller: Address) -> Result<U256, MerchantPayError> {
+        let listing = self.listings.getter(id).getter(seller).get();
+        if listing.seller == Address::ZERO {
+            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        }
+        Ok(listing.rate_at(U256::from(block::timestamp())))
+    }
+
+    /// Deletes one of the caller's listings and drops it from both indexes.
+    /// Fails while any escrowed order for the listing is still open.
+    pub fn remove_listing(&mut self, id: B256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.pausable.when_not_paused(PAUSE_LISTINGS)?;
+        let seller = msg::sender();
+        let listing = self.listings.getter(id).getter(seller).get();
+        if listing.seller == Address::ZERO {
+            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        }
+        if listing.open_escrows != U256::ZERO {
+            return Err(MerchantPayError::UnsettledOrders(UnsettledOrders{}));
+        }
+
+        self.listings.setter(id).setter(seller).set(Listing::default());
+        self.address_to_listing.setter(seller).remove(id);
+
+        // Only drop the id from the global set once no other seller uses it
+        let refs = self.listing_key_refs.get(id) - U256::from(1);
+        self.listing_key_refs.insert(id, refs);
+        if refs == U256::ZERO {
+            self.listing_keys.remove(id);
+        }
+
+        evm::log(ListingRemoved { id, seller });
+        Ok(())
+    }
+
+    /// Publishes a subscription plan billing `amount` of `token` every `period` seconds.
+    /// Failed charges can be retried for `grace_period` seconds before the subscription lapses.
+    pub fn create_plan(
+        &mut self,
+        token: Address,
+        amount: U256,
+        period: U256,
+        grace_period: U256
+    ) -> Result<U256, MerchantPayError> {
+        self.reentrancy.check()?;
+        self.pausable.when_not_paused(PAUSE_LISTINGS)?;
+        // Plans are billed through allowances, so they cannot be priced in the native asset
+        if token == NATIVE_TOKEN || !self.accepted_tokens.get(token) {
+            return Err(MerchantPayError::TokenNotAccepted(TokenNotAccepted{}));
+        }
+        self.subscriptions.create_plan(msg::sender(), token, amount, period, grace_period)
+    }
+
+    /// Stops new subscriptions to one of the caller's plans
+    pub fn deactivate_plan(&mut self, plan_id: U256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.subscriptions.deactivate_plan(msg::sender(), plan_id)
+    }
+
+    /// Subscribes the caller to `plan_id` and charges the first period straight away.
+    /// The caller must have approved this contract to spend the plan amount every period.
+    pub fn subscribe(&mut self, plan_id: U256) -> Result<U256, MerchantPayError> {
+        self.pausable.when_not_paused(PAUSE_PAYMENTS)?;
+        self.reentrancy.enter()?;
+
+        let plan = self.subscriptions.plan(plan_id)?;
+        let now = U256::from(block::timestamp());
+        let subscription_id = self.subscriptions.open(plan_id, msg::sender(), now)?;
+
+        // Book the first period before pulling payment
+        let fee = self.fees.fee_for(plan.merchant(), plan.token(), plan.amount())?;
+        self.subscriptions.record_charge(subscription_id, fee, now)?;
+
+        let received = self.pull_payment(plan.token(), msg::sender(), plan.amount())?;
+        self.settle_subscription_charge(&plan, subscription_id, fee, received)?;
+
+        self.reentrancy.exit();
+        Ok(subscription_id)
+    }
+
+    /// Bills the current period of a subscription; anyone may call this once it is due.
+    /// A charge that cannot be collected is recorded rather than reverted: the subscription
+    /// becomes past due, or lapses if its grace period is over. Returns whether it was paid.
+    pub fn charge_subscription(&mut self, subscription_id: U256) -> Result<bool, MerchantPayError> {
+        self.pausable.when_not_paused(PAUSE_PAYMENTS)?;
+        let subscription = self.subscriptions.subscription(subscription_id)?;
+        if !subscription.is_live() {
+            return Err(MerchantPayError::SubscriptionInactive(SubscriptionInactive{}));
+        }
+
+        let now = U256::from(block::timestamp());
+        if now < subscription.next_charge_at() {
+            return Err(MerchantPayError::ChargeNotDue(ChargeNotDue{}));
+        }
+
+        // Once the grace period has run out the charge is forfeited, not collected late
+        if self.subscriptions.is_past_grace(&subscription, now)? {
+            self.subscriptions.lapse(subscription_id)?;
+            return Ok(false);
+        }
+
+        self.reentrancy.enter()?;
+
+        // Whether the pull succeeds decides what gets recorded, so it has to come first;
+        // the lock keeps the token from re-entering in the meantime
+        let plan = self.subscriptions.plan(subscription.plan_id())?;
+        let before = self.token_balance(plan.token())?;
+        let pulled = self.token_transfer_from(
+            plan.token(),
+            subscription.subscriber(),
+            contract::address(),
+            plan.amount(),
+        );
+        if pulled.is_err() {
+            self.subscriptions.record_missed_charge(subscription_id)?;
+            self.reentrancy.exit();
+            return Ok(false);
+        }
+        let received = self.received_since(plan.token(), before, plan.amount())?;
+
+        let fee = self.fees.fee_for(plan.merchant(), plan.token(), plan.amount())?;
+        self.subscriptions.record_charge(subscription_id, fee, now)?;
+        self.settle_subscription_charge(&plan, subscription_id, fee, received)?;
+
+        self.reentrancy.exit();
+        Ok(true)
+    }
+
+    /// Cancels a subscription; callable by its subscriber or merchant
+    pub fn cancel_subscription(&mut self, subscription_id: U256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.subscriptions.cancel(msg::sender(), subscription_id)
+    }
+
+    pub fn get_plan(&self, plan_id: U256) -> Result<Plan, MerchantPayError> {
+        self.subscriptions.plan(plan_id)
+    }
+
+    pub fn get_subscription(&self, subscription_id: U256) -> Result<Subscription, MerchantPayError> {
+        self.subscriptions.subscription(subscription_id)
+    }
+
+    /// Bills `buyer` (or anyone, if `buyer` is zero) for `amount` of `token`. Once `due_date`
+    /// has passed, `late_fee` is added to what is owed; a zero `due_date` never falls due.
+    pub fn create_invoice(
+        &mut self,
+        buyer: Address,
+        token: Address,
+        amount: U256,
+        due_date: U256,
+        reference: B256,
+        late_fee: U256
+    ) -> Result<U256, MerchantPayError> {
+        self.reentrancy.check()?;
+        self.pausable.when_not_paused(PAUSE_LISTINGS)?;
+        if !self.accepted_tokens.get(token) {
+            return Err(MerchantPayError::TokenNotAccepted(TokenNotAccepted{}));
+        }
+        self.invoices.create(msg::sender(), buyer, token, amount, due_date, reference, late_fee)
+    }
+
+    /// Settles an open invoice. Native invoices are paid with msg::value() and any excess
+    /// is refunded; token invoices are pulled through the caller's allowance.
+    #[payable]
+    pub fn pay_invoice(&mut self, invoice_id: U256) -> Result<(), MerchantPayError> {
+        self.pausable.when_not_paused(PAUSE_PAYMENTS)?;
+        let payer = msg::sender();
+        let invoice = self.invoices.payable(invoice_id, payer)?;
+        let token = invoice.token();
+        if !self.accepted_tokens.get(token) {
+            return Err(MerchantPayError::TokenNotAccepted(TokenNotAccepted{}));
+        }
+
+        let now = U256::from(block::timestamp());
+        let amount = invoice.amount_due_at(now);
+        let native = token == NATIVE_TOKEN;
+        if native && msg::value() < amount {
+            return Err(MerchantPayError::InvalidAmount(InvalidAmount{}));
+        }
+        if !native && msg::value() != U256::ZERO {
+            return Err(MerchantPayError::WrongPaymentMethod(WrongPaymentMethod{}));
+        }
+
+        self.reentrancy.enter()?;
+
+        let fee = self.fees.fee_for(invoice.issuer(), token, amount)?;
+        let reference = B256::from(invoice_id.to_be_bytes::<32>());
+        self.invoices.mark_paid(invoice_id, payer, amount, fee, now)?;
+        self.pay_direct(token, payer, invoice.issuer(), amount, fee, reference, FeeSource::Invoice)?;
+
+        // Return any excess ETH
+        if native && msg::value() > amount {
+            self.token_transfer(NATIVE_TOKEN, payer, msg::value() - amount)?;
+        }
+
+        self.reentrancy.exit();
+        Ok(())
+    }
+
+    /// Voids one of the caller's unpaid invoices
+    pub fn void_invoice(&mut self, invoice_id: U256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.invoices.void(msg::sender(), invoice_id)
+    }
+
+    pub fn get_invoice(&self, invoice_id: U256) -> Result<Invoice, MerchantPayError> {
+        self.invoices.invoice(invoice_id)
+    }
+
+    /// Amount currently owed on an invoice, including any late fee
+    pub fn invoice_amount_due(&self, invoice_id: U256) -> Result<U256, MerchantPayError> {
+        let invoice = self.invoices.invoice(invoice_id)?;
+        Ok(invoice.amount_due_at(U256::from(block::timestamp())))
+    }
+
+    /// Buys `buy_quantity` units against an offer the seller signed off-chain (EIP-712), plus
+    /// `tip` for the seller, charging the fee on both as `pay_for_listing` does. The signed rate
+    /// fixes the price, so no spending bound is needed. Offers are settled directly and must be
+    /// priced in an accepted ERC-20 token.
+    #[allow(clippy::too_many_arguments)]
+    pub fn pay_with_signed_offer(
+        &mut self,
+        seller: Address,
+        id: B256,
+        token: Address,
+        rate: U256,
+        quantity: U256,
+        expiry: U256,
+        nonce: U256,
+        buy_quantity: U256,
+        tip: U256,
+        v: u8,
+        r: B256,
+        s: B256
+    ) -> Result<U256, MerchantPayError> {
+        self.pausable.when_not_paused(PAUSE_PAYMENTS)?;
+        let offer = Offer { seller, id, token, rate, quantity, expiry, nonce };
+
+        let now = U256::from(block::timestamp());
+        if now > expiry {
+            return Err(MerchantPayError::OfferExpired(OfferExpired{}));
+        }
+        if !self.accepted_tokens.get(token) {
+            return Err(MerchantPayError::TokenNotAccepted(TokenNotAccepted{}));
+        }
+        if token == NATIVE_TOKEN {
+            return Err(MerchantPayError::WrongPaymentMethod(WrongPaymentMethod{}));
+        }
+
+        // Verify the seller signed these exact terms
+        let offer_hash = offer.struct_hash();
+        let digest = eip712::hash_typed_data(self.domain_separator(), offer_hash);
+        if eip712::recover(digest, v, r, s) != Some(seller) {
+            return Err(MerchantPayError::InvalidSignature(InvalidSignature{}));
+        }
+
+        self.offers.fill(&offer, offer_hash, buy_quantity)?;
+
+        let price = rate
+            .checked_mul(buy_quantity)
+            .ok_or(MerchantPayError::InvalidAmount(InvalidAmount{}))?;
+        let amount = price
+            .checked_add(tip)
+            .ok_or(MerchantPayError::InvalidAmount(InvalidAmount{}))?;
+        let charge = self.fees.fee_for(seller, token, amount)?;
+
+        self.reentrancy.enter()?;
+
+        let order_id = self.next_order_id();
+        self.record_order(Order {
+            id: order_id,
+            listing_id: id,
+            seller,
+            buyer: msg::sender(),
+            token,
+            quantity: buy_quantity,
+            rate,
+            amount: price,
+            tip,
+            fee: charge,
+            timestamp: now,
+            status: OrderStatus::SETTLED,
+        });
+        self.pay_direct(token, msg::sender(), seller, amount, charge, id, FeeSource::Offer)?;
+
+        evm::log(ListingPaid {
+            id,
+            seller,
+            buyer: msg::sender(),
+            orderId: order_id,
+            price,
+            tip,
+            fee: charge,
+            quantity: buy_quantity,
+        });
+
+        self.reentrancy.exit();
+        Ok(order_id)
+    }
+
+    /// Invalidates every offer the caller signed with `nonce`
+    pub fn cancel_offer_nonce(&mut self, nonce: U256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.offers.cancel(msg::sender(), nonce);
+        Ok(())
+    }
+
+    pub fn is_offer_nonce_cancelled(&self, seller: Address, nonce: U256) -> bool {
+        self.offers.is_cancelled(seller, nonce)
+    }
+
+    /// Quantity already sold against the offer with struct hash `offer_hash`
+    pub fn offer_filled(&self, offer_hash: B256) -> U256 {
+        self.offers.filled(offer_hash)
+    }
+
+    /// EIP-712 struct hash of an offer, as tracked by `offer_filled`
+    #[allow(clippy::too_many_arguments)]
+    pub fn hash_offer(
+        &self,
+        seller: Address,
+        id: B256,
+        token: Address,
+        rate: U256,
+        quantity: U256,
+        expiry: U256,
+        nonce: U256
+    ) -> B256 {
+        Offer { seller, id, token, rate, quantity, expiry, nonce }.struct_hash()
+    }
+
+    /// EIP-712 domain separator that signed offers are bound to
+    #[selector(name = "DOMAIN_SEPARATOR")]
+    pub fn domain_separator(&self) -> B256 {
+        eip712::domain_separator(DOMAIN_NAME, DOMAIN_VERSION)
+    }
+
+    pub fn treasury(&self) -> Address {
+        self.treasury.get()
+    }
+
+    pub fn set_treasury(&mut self, treasury: Address) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        let previous_treasury = self.treasury.get();
+        self.treasury.set(treasury);
+
+        evm::log(TreasuryUpdated {
+            previousTreasury: previous_treasury,
+            newTreasury: treasury,
+        });
+        Ok(())
+    }
+
+    /// Platform fees collected in `token` that have not yet been withdrawn
+    pub fn accrued_fees(&self, token: Address) -> U256 {
+        self.collected_fees.get(token)
+    }
+
+    /// Sends collected fees to `to`; callable by the owner or the treasury
+    pub fn withdraw_fees(&mut self, token: Address, to: Address, amount: U256) -> Result<(), MerchantPayError> {
+        let caller = msg::sender();
+        if caller != self.owner.get() && caller != self.treasury.get() {
+            return Err(MerchantPayError::Unauthorized(Unauthorized{}));
+        }
+        if to == Address::ZERO || amount == U256::ZERO {
+            return Err(MerchantPayError::InvalidAmount(InvalidAmount{}));
+        }
+
+        let available = self.collected_fees.get(token);
+        if amount > available {
+            return Err(MerchantPayError::InsufficientFees(InsufficientFees{}));
+        }
+        self.reentrancy.enter()?;
+        self.collected_fees.insert(token, available - amount);
+
+        self.token_transfer(token, to, amount)?;
+
+        evm::log(FeesWithdrawn {
+            token,
+            to,
+            caller,
+            amount,
+        });
+
+        self.reentrancy.exit();
+        Ok(())
+    }
+
+    pub fn get_order(&self, order_id: U256) -> Result<Order, MerchantPayError> {
+        let order = self.orders.getter(order_id).get();
+        if order.buyer == Address::ZERO {
+            return Err(MerchantPayError::OrderNotFound(OrderNotFound{}));
+        }
+        Ok(order)
+    }
+
+    pub fn get_listing(&self, id: B256, seller: Address) -> Result<Listing, MerchantPayError> {
+        let listing = self.listings.getter(id).getter(seller).get();
+        if listing.seller == Address::ZERO {
+            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        }
+        Ok(listing)
+    }
+
+    pub fn get_all_listings_for_address(&self, seller: Address) -> Result<Vec<Listing>, MerchantPayError> {
+        let mut listings = Vec::new();
+        let bytes_keys = self.address_to_listing.getter(seller);
+        
+        for i in 0..bytes_keys.len() {
+            if let Some(id) = bytes_keys.get(i) {
+                let listing = self.listings.getter(id).getter(seller).get();
+                if listing.seller != Address::ZERO {
+                    listings.push(listing);
+                }
+            }
+        }
+        
+        if listings.is_empty() {
+            return Err(MerchantPayError::ListingNotFound(ListingNotFound{}));
+        }
+        
+        Ok(listings)
+    }
+
+    /// Walks a seller's catalogue one page at a time. `limit` caps the number of entries scanned
+    /// (at most `MAX_PAGE_SIZE`, which zero also selects), so a page filtered by `status` may hold fewer than `limit` listings.
+    /// Returns the listings and the cursor for the next page, which is zero once the end is reached.
+    pub fn get_listings_for_address_paginated(
+        &self,
+        seller: Address,
+        cursor: U256,
+        limit: U256,
+        filter_by_status: bool,
+        status: u8
+    ) -> Result<(Vec<Listing>, U256), MerchantPayError> {
+        let mut listings = Vec::new();
+        let bytes_keys = self.address_to_listing.getter(seller);
+        let (start, end) = Self::page_bounds(bytes_keys.len(), cursor, limit);
+
+        for i in start..end {
+            if let Some(id) = bytes_keys.get(i) {
+                let listing = self.listings.getter(id).getter(seller).get();
+                if listing.seller == Address::ZERO {
+                    continue;
+                }
+                if filter_by_status && listing.status as u8 != status {
+                    continue;
+                }
+                listings.push(listing);
+            }
+        }
+
+        Ok((listings, Self::next_cursor(bytes_keys.len(), end)))
+    }
+
+    /// Walks the global set of listing ids one page at a time, returning the ids and the next cursor.
+    /// There is no status filter here: an id can carry listings from several sellers, each with its
+    /// own status, so resolve ids with `get_listing` or filter through a seller's paginated view.
+    pub fn get_listing_keys(&self, cursor: U256, limit: U256) -> (Vec<B256>, U256) {
+        let mut keys = Vec::new();
+        let (start, end) = Self::page_bounds(self.listing_keys.len(), cursor, limit);
+
+        for i in start..end {
+            if let Some(id) = self.listing_keys.get(i) {
+                keys.push(id);
+            }
+        }
+
+        (keys, Self::next_cursor(self.listing_keys.len(), end))
+    }
+
+    pub fn listing_keys_count(&self) -> U256 {
+        U256::from(self.listing_keys.len())
+    }
+
+    pub fn get_purchases_for_buyer(&self, buyer: Address) -> Result<Vec<Order>, MerchantPayError> {
+        let mut orders = Vec::new();
+        let order_ids = self.buyer_orders.getter(buyer);
+
+        for i in 0..order_ids.len() {
+            if let Some(order_id) = order_ids.get(i) {
+                let order = self.orders.getter(order_id).get();
+                if order.buyer != Address::ZERO {
+                    orders.push(order);
+                }
+            }
+        }
+
+        if orders.is_empty() {
+            return Err(MerchantPayError::OrderNotFound(OrderNotFound{}));
+        }
+
+        Ok(orders)
+    }
+
+    pub fn fee_rate(&self) -> U256 {
+        self.fees.rate()
+    }
+
+    pub fn seller_fee_tier(&self, seller: Address) -> U256 {
+        self.fees.tier_of(seller)
+    }
+
+    /// Fee that would be charged to `seller` on a payment of `amount` in `token`
+    pub fn effective_fee(&self, seller: Address, token: Address, amount: U256) -> Result<U256, MerchantPayError> {
+        self.fees.fee_for(seller, token, amount)
+    }
+
+    /// Sets the global fee rate in basis points
+    pub fn set_fee_rate(&mut self, rate_bps: U256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        self.fees.set_rate(rate_bps)
+    }
+
+    /// Defines a fee tier that can be assigned to individual sellers
+    pub fn set_fee_tier(&mut self, tier: U256, rate_bps: U256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        self.fees.set_tier(tier, rate_bps)
+    }
+
+    /// Assigns `seller` to `tier`; tier zero reverts them to the global rate
+    pub fn set_seller_fee_tier(&mut self, seller: Address, tier: U256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        self.fees.set_seller_tier(seller, tier)
+    }
+
+    /// Sets the minimum fee and the fee cap (zero for no cap) for `token`
+    pub fn set_fee_bounds(&mut self, token: Address, min_fee: U256, max_fee: U256) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        self.fees.set_bounds(token, min_fee, max_fee)
+    }
+
+    pub fn is_token_accepted(&self, token: Address) -> bool {
+        self.accepted_tokens.get(token)
+    }
+
+    pub fn is_fee_on_transfer(&self, token: Address) -> bool {
+        self.fee_on_transfer_tokens.get(token)
+    }
+
+    /// Flags `token` as taking a cut of each transfer. Payments in a flagged token are
+    /// accounted on the amount that actually arrives, with the shortfall borne by the payee;
+    /// any other token that delivers short is rejected.
+    pub fn set_fee_on_transfer(&mut self, token: Address, flagged: bool) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        self.fee_on_transfer_tokens.insert(token, flagged);
+
+        evm::log(FeeOnTransferUpdated { token, flagged });
+        Ok(())
+    }
+
+    /// Adds `token` to or removes it from the payment token whitelist
+    pub fn set_token_accepted(&mut self, token: Address, accepted: bool) -> Result<(), MerchantPayError> {
+        self.reentrancy.check()?;
+        self.only_owner()?;
+        self.accepted_tokens.insert(token, accepted);
+
+        evm::log(PaymentTokenUpdated { token, accepted });
+        Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,97 @@
+//! EIP-712 signed listing offers
+//!
+//! Instead of calling `add_listing`, a seller can sign an [`Offer`] off-chain
+//! and hand it to buyers, who redeem it with `pay_with_signed_offer`. Nothing
+//! is stored for an offer until it is first used; from then on the quantity
+//! sold against it is tracked by its struct hash. Sellers retire offers by
+//! cancelling their nonce, which invalidates every offer signed with it.
+
+// Imported packages
+use alloy_primitives::{Address, B256, U256};
+use alloy_sol_types::sol;
+use stylus_sdk::{crypto::keccak, evm, prelude::*};
+
+use crate::eip712::{encode_words, word};
+use crate::{InvalidQuantity, MerchantPayError, OfferCancelled};
+
+/// Name used in the EIP-712 domain
+pub const DOMAIN_NAME: &str = "MerchantPay";
+
+/// Version used in the EIP-712 domain
+pub const DOMAIN_VERSION: &str = "1";
+
+/// EIP-712 type of a signed offer
+const OFFER_TYPE: &str =
+    "Offer(address seller,bytes32 id,address token,uint256 rate,uint256 quantity,uint256 expiry,uint256 nonce)";
+
+/// Terms a seller signs off-chain
+pub struct Offer {
+    pub seller: Address,
+    pub id: B256,
+    pub token: Address,
+    pub rate: U256,
+    pub quantity: U256,
+    pub expiry: U256,
+    pub nonce: U256,
+}
+
+impl Offer {
+    /// EIP-712 `hashStruct` of the offer
+    pub fn struct_hash(&self) -> B256 {
+        keccak(encode_words(&[
+            keccak(OFFER_TYPE.as_bytes()),
+            self.seller.into_word(),
+            self.id,
+            self.token.into_word(),
+            word(self.rate),
+            word(self.quantity),
+            word(self.expiry),
+            word(self.nonce),
+        ]))
+    }
+}
+
+sol_storage! {
+    /// SignedOffers tracks usage and cancellation of signed offers.
+    pub struct SignedOffers {
+        /// Quantity sold against each offer, by struct hash
+        mapping(bytes32 => uint256) filled;
+        /// Nonces each seller has cancelled
+        mapping(address => mapping(uint256 => bool)) cancelled_nonces;
+    }
+}
+
+// Declare events
+sol! {
+    event OfferNonceCancelled(address indexed seller, uint256 indexed nonce);
+}
+
+impl SignedOffers {
+    /// Quantity already sold against the offer with `offer_hash`
+    pub fn filled(&self, offer_hash: B256) -> U256 {
+        self.filled.get(offer_hash)
+    }
+
+    pub fn is_cancelled(&self, seller: Address, nonce: U256) -> bool {
+        self.cancelled_nonces.getter(seller).get(nonce)
+    }
+
+    /// Invalidates every offer `seller` signed with `nonce`
+    pub fn cancel(&mut self, seller: Address, nonce: U256) {
+        self.cancelled_nonces.setter(seller).insert(nonce, true);
+        evm::log(OfferNonceCancelled { seller, nonce });
+    }
+
+    /// Records the sale of `quantity` units against `offer`
+    pub fn fill(&mut self, offer: &Offer, offer_hash: B256, quantity: U256) -> Result<(), MerchantPayError> {
+        if self.is_cancelled(offer.seller, offer.nonce) {
+            return Err(MerchantPayError::OfferCancelled(OfferCancelled{}));
+        }
+        let filled = self.filled.get(offer_hash) + quantity;
+        if quantity == U256::ZERO || filled > offer.quantity {
+            return Err(MerchantPayError::InvalidQuantity(InvalidQuantity{}));
+        }
+        self.filled.insert(offer_hash, filled);
+        Ok(())
+    }
+}
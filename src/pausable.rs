@@ -0,0 +1,95 @@
+//! Emergency pause switch
+//!
+//! [`Pausable`] lets a dedicated pauser (or the owner) halt parts of
+//! MerchantPay while an incident is investigated. Pausing is granular:
+//! payments and listing management can be stopped separately or together,
+//! expressed as a bitmask of [`PAUSE_PAYMENTS`] and [`PAUSE_LISTINGS`].
+//! Views are never paused.
+
+// Imported packages
+use alloy_primitives::{Address, U8};
+use alloy_sol_types::sol;
+use stylus_sdk::{evm, prelude::*};
+
+use crate::{ContractPaused, InvalidPauseFlags, MerchantPayError};
+
+/// Halts purchases, escrow releases, subscription charges and invoice payments
+pub const PAUSE_PAYMENTS: u8 = 1;
+
+/// Halts creating and editing listings, plans and invoices
+pub const PAUSE_LISTINGS: u8 = 2;
+
+/// Halts everything above
+pub const PAUSE_ALL: u8 = PAUSE_PAYMENTS | PAUSE_LISTINGS;
+
+sol_storage! {
+    /// Pausable holds the pauser role and what is currently paused.
+    pub struct Pausable {
+        /// Account allowed to pause and unpause besides the owner
+        address pauser;
+        /// Bitmask of paused areas
+        uint8 paused;
+    }
+}
+
+// Declare events
+sol! {
+    event Paused(address indexed account, uint8 flags);
+    event Unpaused(address indexed account, uint8 flags);
+    event PauserUpdated(address indexed previousPauser, address indexed newPauser);
+}
+
+impl Pausable {
+    pub fn pauser(&self) -> Address {
+        self.pauser.get()
+    }
+
+    /// Bitmask of the areas currently paused
+    pub fn paused(&self) -> u8 {
+        self.paused.get().to::<u8>()
+    }
+
+    /// Fails if any area in `flags` is paused
+    pub fn when_not_paused(&self, flags: u8) -> Result<(), MerchantPayError> {
+        let paused = self.paused();
+        if paused & flags != 0 {
+            return Err(MerchantPayError::ContractPaused(ContractPaused { flags: paused }));
+        }
+        Ok(())
+    }
+
+    pub fn set_pauser(&mut self, pauser: Address) {
+        let previous_pauser = self.pauser.get();
+        self.pauser.set(pauser);
+
+        evm::log(PauserUpdated {
+            previousPauser: previous_pauser,
+            newPauser: pauser,
+        });
+    }
+
+    /// Pauses the areas in `flags` on behalf of `account`
+    pub fn pause(&mut self, account: Address, flags: u8) -> Result<(), MerchantPayError> {
+        Self::check_flags(flags)?;
+        self.paused.set(U8::from(self.paused() | flags));
+
+        evm::log(Paused { account, flags });
+        Ok(())
+    }
+
+    /// Resumes the areas in `flags` on behalf of `account`
+    pub fn unpause(&mut self, account: Address, flags: u8) -> Result<(), MerchantPayError> {
+        Self::check_flags(flags)?;
+        self.paused.set(U8::from(self.paused() & !flags));
+
+        evm::log(Unpaused { account, flags });
+        Ok(())
+    }
+
+    fn check_flags(flags: u8) -> Result<(), MerchantPayError> {
+        if flags == 0 || flags & !PAUSE_ALL != 0 {
+            return Err(MerchantPayError::InvalidPauseFlags(InvalidPauseFlags{}));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+//! Storage-backed reentrancy lock
+//!
+//! The SDK is built without its `reentrant` feature, so any call that re-enters
+//! MerchantPay is already rejected by the entrypoint before it reaches our code.
+//! [`ReentrancyGuard`] is a second layer on top of that, in case the feature is
+//! ever enabled: it is held for the duration of any entrypoint that calls out
+//! to another contract, and every other state-changing entrypoint checks that
+//! it is free. The flag lives in storage, so a reverted call also rolls back
+//! the lock; only successful returns need to release it with
+//! [`ReentrancyGuard::exit`].
+
+// Imported packages
+use stylus_sdk::prelude::*;
+
+use crate::{MerchantPayError, ReentrantCall};
+
+sol_storage! {
+    /// ReentrancyGuard records whether a guarded call is in progress.
+    pub struct ReentrancyGuard {
+        /// Set while a guarded call is executing
+        bool entered;
+    }
+}
+
+impl ReentrancyGuard {
+    /// Fails if a guarded call is in progress
+    pub fn check(&self) -> Result<(), MerchantPayError> {
+        if self.entered.get() {
+            return Err(MerchantPayError::ReentrantCall(ReentrantCall{}));
+        }
+        Ok(())
+    }
+
+    /// Takes the lock
+    pub fn enter(&mut self) -> Result<(), MerchantPayError> {
+        self.check()?;
+        self.entered.set(true);
+        Ok(())
+    }
+
+    /// Releases the lock
+    pub fn exit(&mut self) {
+        self.entered.set(false);
+    }
+}
@@ -0,0 +1,87 @@
+//! Checked ERC-20 transfers
+//!
+//! Not every token follows the ERC-20 spec to the letter: some return `false`
+//! instead of reverting, and some (USDT being the best known) return nothing
+//! at all. These helpers call the token directly and only accept a transfer
+//! that either returned `true` or returned no data from a contract account.
+//! On failure the token's revert data is carried in `TransferFailed`.
+
+// Imported packages
+use alloc::vec::Vec;
+use alloy_primitives::{Address, B256, U256};
+use stylus_sdk::{
+    call::{call, Call, Error},
+    prelude::*,
+    types::AddressVM,
+};
+
+use crate::eip712::{encode_words, word};
+use crate::{MerchantPayError, TransferFailed};
+
+/// `transfer(address,uint256)`
+const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+/// `transferFrom(address,address,uint256)`
+const TRANSFER_FROM_SELECTOR: [u8; 4] = [0x23, 0xb8, 0x72, 0xdd];
+
+/// Moves `amount` of `token` held by the caller's contract to `to`
+pub fn safe_transfer(
+    storage: &mut impl TopLevelStorage,
+    token: Address,
+    to: Address,
+    amount: U256,
+) -> Result<(), MerchantPayError> {
+    let args = [to.into_word(), word(amount)];
+    call_token(storage, token, TRANSFER_SELECTOR, &args)
+}
+
+/// Moves `amount` of `token` from `from` to `to` using the caller's allowance
+pub fn safe_transfer_from(
+    storage: &mut impl TopLevelStorage,
+    token: Address,
+    from: Address,
+    to: Address,
+    amount: U256,
+) -> Result<(), MerchantPayError> {
+    let args = [from.into_word(), to.into_word(), word(amount)];
+    call_token(storage, token, TRANSFER_FROM_SELECTOR, &args)
+}
+
+/// Builds the `TransferFailed` error for `token`
+pub fn transfer_failed(token: Address, reason: Vec<u8>) -> MerchantPayError {
+    MerchantPayError::TransferFailed(TransferFailed {
+        token,
+        reason: reason.into(),
+    })
+}
+
+/// Calls `selector` on `token` and checks the result the way OpenZeppelin's SafeERC20 does
+fn call_token(
+    storage: &mut impl TopLevelStorage,
+    token: Address,
+    selector: [u8; 4],
+    args: &[B256],
+) -> Result<(), MerchantPayError> {
+    let mut calldata = selector.to_vec();
+    calldata.extend(encode_words(args));
+
+    let output = match call(Call::new_in(storage), token, &calldata) {
+        Ok(output) => output,
+        Err(Error::Revert(reason)) => return Err(transfer_failed(token, reason)),
+        Err(Error::AbiDecodingFailed(_)) => return Err(transfer_failed(token, Vec::new())),
+    };
+
+    // No return data is accepted from tokens that omit the bool, but not from
+    // an address without code, where every call trivially succeeds
+    if output.is_empty() {
+        if token.has_code() {
+            return Ok(());
+        }
+        return Err(transfer_failed(token, Vec::new()));
+    }
+
+    if output.len() < 32 || B256::from_slice(&output[..32]) != word(U256::from(1)) {
+        return Err(transfer_failed(token, Vec::new()));
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+//! Enumerable set of `bytes32` values
+//!
+//! [`Bytes32Set`] keeps its members in an array so they can be enumerated,
+//! alongside a position mapping and an existence flag so that membership
+//! checks, inserts and removals cost the same no matter how large the set is.
+//! Removal swaps the last member into the freed slot, so member order is not
+//! preserved.
+
+// Imported packages
+use alloy_primitives::{B256, U256};
+use stylus_sdk::prelude::*;
+
+sol_storage! {
+    /// Bytes32Set is an unordered set with constant-time operations.
+    pub struct Bytes32Set {
+        /// Members, in no particular order
+        bytes32[] values;
+        /// Maps members to their position in `values`
+        mapping(bytes32 => uint256) index;
+        /// Whether a value is a member
+        mapping(bytes32 => bool) exists;
+    }
+}
+
+impl Bytes32Set {
+    /// Whether `value` is in the set
+    pub fn contains(&self, value: B256) -> bool {
+        self.exists.get(value)
+    }
+
+    /// Number of members
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether the set has no members
+    pub fn is_empty(&self) -> bool {
+        self.values.len() == 0
+    }
+
+    /// Member at position `i`
+    pub fn get(&self, i: usize) -> Option<B256> {
+        self.values.get(i)
+    }
+
+    /// Adds `value`, returning false if it was already present
+    pub fn insert(&mut self, value: B256) -> bool {
+        if self.exists.get(value) {
+            return false;
+        }
+        self.index.insert(value, U256::from(self.values.len()));
+        self.exists.insert(value, true);
+        self.values.push(value);
+        true
+    }
+
+    /// Removes `value`, returning false if it was not present
+    pub fn remove(&mut self, value: B256) -> bool {
+        if !self.exists.get(value) {
+            return false;
+        }
+
+        // Move the last member into the removed member's slot
+        let position = self.index.get(value);
+        let last_position = self.values.len() - 1;
+        if position != U256::from(last_position) {
+            if let Some(last) = self.values.get(last_position) {
+                if let Some(mut slot) = self.values.setter(position) {
+                    slot.set(last);
+                }
+                self.index.insert(last, position);
+            }
+        }
+        self.values.pop();
+
+        self.index.delete(value);
+        self.exists.delete(value);
+        true
+    }
+}
@@ -0,0 +1,312 @@
+//! Recurring subscriptions
+//!
+//! Merchants publish a [`Plan`] (token, amount, billing period and grace
+//! period) and buyers open a [`Subscription`] against it after granting the
+//! contract an allowance. Each period the subscription can be charged once by
+//! anyone, typically a keeper. A failed charge marks it `OVERDUE` and it may
+//! be retried until the grace period runs out. After that the subscription is
+//! `LAPSED` by the next charge attempt, without pulling the missed payment.
+//!
+//! No tokens move in this module. `charge_subscription` in `MerchantPay`
+//! attempts the pull and reports the outcome through
+//! [`Subscriptions::record_charge`] or [`Subscriptions::record_missed_charge`].
+
+// Imported packages
+use alloy_primitives::{Address, U256};
+use alloy_sol_types::sol;
+use stylus_sdk::{evm, prelude::*};
+
+use crate::{
+    InvalidPlan, MerchantPayError, PlanNotFound, SubscriptionInactive, SubscriptionNotFound,
+    Unauthorized,
+};
+
+// Define Plan struct
+#[derive(Default, Clone, StorageType)]
+pub struct Plan {
+    id: U256,
+    merchant: Address,
+    token: Address,
+    amount: U256,
+    period: U256,
+    grace_period: U256,
+    active: bool,
+}
+
+// Define SubscriptionStatus enum
+#[derive(Default, Clone, Copy, PartialEq, Eq, StorageType)]
+pub enum SubscriptionStatus {
+    #[default]
+    ACTIVE,
+    OVERDUE,
+    CANCELLED,
+    LAPSED,
+}
+
+// Define Subscription struct
+#[derive(Default, Clone, StorageType)]
+pub struct Subscription {
+    id: U256,
+    plan_id: U256,
+    subscriber: Address,
+    merchant: Address,
+    status: SubscriptionStatus,
+    next_charge_at: U256,
+    last_charged_at: U256,
+    charges: U256,
+}
+
+sol_storage! {
+    /// Subscriptions stores plans and the subscriptions opened against them.
+    pub struct Subscriptions {
+        /// Id of the most recently created plan
+        uint256 plan_count;
+        /// Maps plan ids to plans
+        mapping(uint256 => Plan) plans;
+        /// Id of the most recently opened subscription
+        uint256 subscription_count;
+        /// Maps subscription ids to subscriptions
+        mapping(uint256 => Subscription) subscriptions;
+    }
+}
+
+// Declare events
+sol! {
+    event PlanCreated(
+        uint256 indexed planId,
+        address indexed merchant,
+        address indexed token,
+        uint256 amount,
+        uint256 period,
+        uint256 gracePeriod
+    );
+    event PlanDeactivated(uint256 indexed planId);
+    event Subscribed(
+        uint256 indexed subscriptionId,
+        uint256 indexed planId,
+        address indexed subscriber
+    );
+    event SubscriptionCharged(
+        uint256 indexed subscriptionId,
+        uint256 indexed planId,
+        address indexed subscriber,
+        uint256 amount,
+        uint256 fee,
+        uint256 nextChargeAt
+    );
+    event SubscriptionChargeFailed(
+        uint256 indexed subscriptionId,
+        uint256 indexed planId,
+        address indexed subscriber,
+        uint256 graceEndsAt
+    );
+    event SubscriptionLapsed(uint256 indexed subscriptionId, uint256 indexed planId);
+    event SubscriptionCancelled(uint256 indexed subscriptionId, address indexed cancelledBy);
+}
+
+impl Plan {
+    pub fn merchant(&self) -> Address {
+        self.merchant
+    }
+
+    pub fn token(&self) -> Address {
+        self.token
+    }
+
+    pub fn amount(&self) -> U256 {
+        self.amount
+    }
+}
+
+impl Subscription {
+    pub fn plan_id(&self) -> U256 {
+        self.plan_id
+    }
+
+    pub fn subscriber(&self) -> Address {
+        self.subscriber
+    }
+
+    pub fn next_charge_at(&self) -> U256 {
+        self.next_charge_at
+    }
+
+    /// Whether the subscription can still be charged
+    pub fn is_live(&self) -> bool {
+        self.status == SubscriptionStatus::ACTIVE || self.status == SubscriptionStatus::OVERDUE
+    }
+}
+
+impl Subscriptions {
+    /// Publishes a plan for `merchant`; the grace period must be shorter than the billing period
+    pub fn create_plan(
+        &mut self,
+        merchant: Address,
+        token: Address,
+        amount: U256,
+        period: U256,
+        grace_period: U256,
+    ) -> Result<U256, MerchantPayError> {
+        if amount == U256::ZERO || period == U256::ZERO || grace_period >= period {
+            return Err(MerchantPayError::InvalidPlan(InvalidPlan{}));
+        }
+
+        let plan_id = self.plan_count.get() + U256::from(1);
+        self.plan_count.set(plan_id);
+        self.plans.setter(plan_id).set(Plan {
+            id: plan_id,
+            merchant,
+            token,
+            amount,
+            period,
+            grace_period,
+            active: true,
+        });
+
+        evm::log(PlanCreated {
+            planId: plan_id,
+            merchant,
+            token,
+            amount,
+            period,
+            gracePeriod: grace_period,
+        });
+        Ok(plan_id)
+    }
+
+    /// Stops new subscriptions to a plan; existing subscriptions keep billing
+    pub fn deactivate_plan(&mut self, caller: Address, plan_id: U256) -> Result<(), MerchantPayError> {
+        let mut plan = self.plan(plan_id)?;
+        if plan.merchant != caller {
+            return Err(MerchantPayError::Unauthorized(Unauthorized{}));
+        }
+        plan.active = false;
+        self.plans.setter(plan_id).set(plan);
+
+        evm::log(PlanDeactivated { planId: plan_id });
+        Ok(())
+    }
+
+    pub fn plan(&self, plan_id: U256) -> Result<Plan, MerchantPayError> {
+        let plan = self.plans.getter(plan_id).get();
+        if plan.merchant == Address::ZERO {
+            return Err(MerchantPayError::PlanNotFound(PlanNotFound{}));
+        }
+        Ok(plan)
+    }
+
+    pub fn subscription(&self, subscription_id: U256) -> Result<Subscription, MerchantPayError> {
+        let subscription = self.subscriptions.getter(subscription_id).get();
+        if subscription.subscriber == Address::ZERO {
+            return Err(MerchantPayError::SubscriptionNotFound(SubscriptionNotFound{}));
+        }
+        Ok(subscription)
+    }
+
+    /// Opens a subscription to an active plan with its first charge due at `now`
+    pub fn open(&mut self, plan_id: U256, subscriber: Address, now: U256) -> Result<U256, MerchantPayError> {
+        let plan = self.plan(plan_id)?;
+        if !plan.active {
+            return Err(MerchantPayError::InvalidPlan(InvalidPlan{}));
+        }
+
+        let subscription_id = self.subscription_count.get() + U256::from(1);
+        self.subscription_count.set(subscription_id);
+        self.subscriptions.setter(subscription_id).set(Subscription {
+            id: subscription_id,
+            plan_id,
+            subscriber,
+            merchant: plan.merchant,
+            status: SubscriptionStatus::ACTIVE,
+            next_charge_at: now,
+            last_charged_at: U256::ZERO,
+            charges: U256::ZERO,
+        });
+
+        evm::log(Subscribed {
+            subscriptionId: subscription_id,
+            planId: plan_id,
+            subscriber,
+        });
+        Ok(subscription_id)
+    }
+
+    /// Whether the grace period for the subscription's current charge has run out at `now`
+    pub fn is_past_grace(&self, subscription: &Subscription, now: U256) -> Result<bool, MerchantPayError> {
+        let plan = self.plan(subscription.plan_id)?;
+        Ok(now > subscription.next_charge_at + plan.grace_period)
+    }
+
+    /// Books a successful charge and moves the next charge to the first period boundary after
+    /// `now`, so periods that went by unbilled are skipped rather than charged back to back
+    pub fn record_charge(&mut self, subscription_id: U256, fee: U256, now: U256) -> Result<(), MerchantPayError> {
+        let mut subscription = self.subscription(subscription_id)?;
+        let plan = self.plan(subscription.plan_id)?;
+
+        let periods = now.saturating_sub(subscription.next_charge_at) / plan.period + U256::from(1);
+        subscription.status = SubscriptionStatus::ACTIVE;
+        subscription.next_charge_at += periods * plan.period;
+        subscription.last_charged_at = now;
+        subscription.charges += U256::from(1);
+        self.subscriptions.setter(subscription_id).set(subscription.clone());
+
+        evm::log(SubscriptionCharged {
+            subscriptionId: subscription_id,
+            planId: plan.id,
+            subscriber: subscription.subscriber,
+            amount: plan.amount,
+            fee,
+            nextChargeAt: subscription.next_charge_at,
+        });
+        Ok(())
+    }
+
+    /// Marks the subscription as having missed its current charge
+    pub fn record_missed_charge(&mut self, subscription_id: U256) -> Result<(), MerchantPayError> {
+        let mut subscription = self.subscription(subscription_id)?;
+        let plan = self.plan(subscription.plan_id)?;
+
+        subscription.status = SubscriptionStatus::OVERDUE;
+        self.subscriptions.setter(subscription_id).set(subscription.clone());
+
+        evm::log(SubscriptionChargeFailed {
+            subscriptionId: subscription_id,
+            planId: plan.id,
+            subscriber: subscription.subscriber,
+            graceEndsAt: subscription.next_charge_at + plan.grace_period,
+        });
+        Ok(())
+    }
+
+    /// Ends a subscription whose grace period ran out without payment
+    pub fn lapse(&mut self, subscription_id: U256) -> Result<(), MerchantPayError> {
+        let mut subscription = self.subscription(subscription_id)?;
+        subscription.status = SubscriptionStatus::LAPSED;
+        self.subscriptions.setter(subscription_id).set(subscription.clone());
+
+        evm::log(SubscriptionLapsed {
+            subscriptionId: subscription_id,
+            planId: subscription.plan_id,
+        });
+        Ok(())
+    }
+
+    /// Cancels a subscription on behalf of its subscriber or merchant
+    pub fn cancel(&mut self, caller: Address, subscription_id: U256) -> Result<(), MerchantPayError> {
+        let mut subscription = self.subscription(subscription_id)?;
+        if caller != subscription.subscriber && caller != subscription.merchant {
+            return Err(MerchantPayError::Unauthorized(Unauthorized{}));
+        }
+        if !subscription.is_live() {
+            return Err(MerchantPayError::SubscriptionInactive(SubscriptionInactive{}));
+        }
+        subscription.status = SubscriptionStatus::CANCELLED;
+        self.subscriptions.setter(subscription_id).set(subscription);
+
+        evm::log(SubscriptionCancelled {
+            subscriptionId: subscription_id,
+            cancelledBy: caller,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! Upgrades behind an ERC-1967 proxy
+//!
+//! MerchantPay is deployed behind `contracts/MerchantPayProxy.sol`, which
+//! delegates every call to the implementation stored in the standard
+//! [ERC-1967](https://eips.ethereum.org/EIPS/eip-1967) slot. Upgrades are
+//! UUPS style: the owner calls `upgrade_to` on the current implementation,
+//! which rewrites that slot. When that succeeds the proxy delegates `migrate`
+//! to the new implementation in the same transaction, so new code never runs
+//! against storage it has not migrated. The proxy does this rather than
+//! `upgrade_to`, since the SDK rejects a program calling back into itself.
+//!
+//! Storage lives in the proxy and survives upgrades as long as the layout only
+//! grows at the end. New fields go after the last field of `MerchantPay`, or
+//! after the last field of a struct kept in a mapping such as `Listing` or
+//! `Order`. Structs embedded directly in `MerchantPay` (`FeeSchedule`,
+//! `Subscriptions`, `Invoices`, `SignedOffers`, `ReentrancyGuard`, `Pausable`
+//! and `Upgrades` itself) must never grow, since that would shift every field
+//! after them. Each release that changes storage bumps [`STORAGE_VERSION`]:
+//!
+//! 1. Introduced versioning.
+//! 2. Appended `listed_at` to `Listing`.
+
+// Imported packages
+use alloy_primitives::{uint, Address, U256};
+use alloy_sol_types::sol;
+use stylus_sdk::{evm, prelude::*, storage::StorageAddress, types::AddressVM};
+
+use crate::{InvalidImplementation, MerchantPayError};
+
+/// bytes32(uint256(keccak256("eip1967.proxy.implementation")) - 1)
+pub const IMPLEMENTATION_SLOT: U256 =
+    uint!(0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc_U256);
+
+/// Storage layout version of this implementation
+pub const STORAGE_VERSION: u64 = 2;
+
+sol_storage! {
+    /// Upgrades tracks which storage version the proxy's state is at.
+    pub struct Upgrades {
+        /// Version the stored data was last migrated to, zero before versioning
+        uint256 storage_version;
+    }
+}
+
+// Declare events
+sol! {
+    event Upgraded(address indexed implementation);
+    event Migrated(uint256 fromVersion, uint256 toVersion);
+}
+
+impl Upgrades {
+    /// Implementation the proxy currently delegates to
+    pub fn implementation(&self) -> Address {
+        Self::implementation_slot().get()
+    }
+
+    /// Points the proxy at `implementation`
+    pub fn upgrade_to(&mut self, implementation: Address) -> Result<(), MerchantPayError> {
+        if !implementation.has_code() {
+            return Err(MerchantPayError::InvalidImplementation(InvalidImplementation{}));
+        }
+        Self::implementation_slot().set(implementation);
+
+        evm::log(Upgraded { implementation });
+        Ok(())
+    }
+
+    pub fn storage_version(&self) -> U256 {
+        self.storage_version.get()
+    }
+
+    /// Marks fresh storage as already at [`STORAGE_VERSION`]
+    pub fn init(&mut self) {
+        self.storage_version.set(U256::from(STORAGE_VERSION));
+    }
+
+    /// Moves the stored version to [`STORAGE_VERSION`] and returns the version it was at,
+    /// or `None` if storage is already current, so each version's migration runs only once
+    pub fn begin_migration(&mut self) -> Option<U256> {
+        let from = self.storage_version.get();
+        let to = U256::from(STORAGE_VERSION);
+        if from >= to {
+            return None;
+        }
+        self.storage_version.set(to);
+
+        evm::log(Migrated {
+            fromVersion: from,
+            toVersion: to,
+        });
+        Some(from)
+    }
+
+    fn implementation_slot() -> StorageAddress {
+        // SAFETY: the ERC-1967 slot is a keccak-derived constant that no
+        // sol_storage! field can be laid out over. Offsets count from the high
+        // end of the word, so the address sits in the low 20 bytes as in Solidity.
+        unsafe { StorageAddress::new(IMPLEMENTATION_SLOT, 32 - 20) }
+    }
+}